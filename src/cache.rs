@@ -0,0 +1,179 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ab_glyph::Font;
+
+use crate::{font::parse_font, FontCow};
+
+const MAGIC: &[u8; 8] = b"HEXVCOV2";
+
+/// The set of characters a font has glyphs for, stored as sorted, inclusive ranges.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    ranges: Vec<(u32, u32)>,
+}
+
+impl Coverage {
+    /// Collects the characters of the font's character map that have a glyph.
+    pub fn from_font<F: Font>(font: &F) -> Self {
+        let mut codepoints: Vec<u32> = font
+            .codepoint_ids()
+            .filter(|(id, _)| id.0 != 0)
+            .map(|(_, char)| char as u32)
+            .collect();
+        codepoints.sort_unstable();
+        codepoints.dedup();
+
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
+        for codepoint in codepoints {
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == codepoint => *end = codepoint,
+                _ => ranges.push((codepoint, codepoint)),
+            }
+        }
+
+        Self { ranges }
+    }
+
+    pub fn contains(&self, char: char) -> bool {
+        let codepoint = char as u32;
+        self.ranges
+            .binary_search_by(|(start, end)| {
+                if *end < codepoint {
+                    std::cmp::Ordering::Less
+                } else if *start > codepoint {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+}
+
+/// Loads the coverage of the face at the index of the font file from the cache, or parses the face and caches its
+/// coverage.
+///
+/// Returns `None` if the font file cannot be read or is not a font.
+pub(crate) fn load_cached(path: &Path, index: u32) -> Option<FontCow<'static>> {
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let cache_path = modified.and_then(|_| cache_file(path, index));
+
+    if let (Some(cache_path), Some(modified)) = (&cache_path, modified) {
+        if let Some(coverage) = read_cache(cache_path, path, index, modified) {
+            return Some(FontCow::Coverage(coverage));
+        }
+    }
+
+    let font = parse_font(fs::read(path).ok()?, index).ok()?;
+
+    if let (Some(cache_path), Some(modified)) = (&cache_path, modified) {
+        // The cache is only an optimization, so failing to write it is not an error
+        let _ = write_cache(cache_path, path, index, modified, &Coverage::from_font(&font));
+    }
+
+    Some(FontCow::FontVec(font))
+}
+
+fn read_cache(cache_path: &Path, font_path: &Path, index: u32, modified: SystemTime) -> Option<Coverage> {
+    let data = fs::read(cache_path).ok()?;
+    let mut data = data.strip_prefix(MAGIC)?;
+    let mut next = |len: usize| -> Option<&[u8]> {
+        let (bytes, rest) = data.split_at_checked(len)?;
+        data = rest;
+        Some(bytes)
+    };
+
+    let path_len = u64::from_le_bytes(next(8)?.try_into().ok()?) as usize;
+    if next(path_len)? != font_path.as_os_str().as_encoded_bytes() {
+        return None;
+    }
+
+    if next(4)? != index.to_le_bytes() {
+        return None;
+    }
+
+    if next(16)? != timestamp(modified) {
+        return None;
+    }
+
+    let count = u64::from_le_bytes(next(8)?.try_into().ok()?) as usize;
+    let mut ranges = Vec::with_capacity(count);
+    for _ in 0..count {
+        let start = u32::from_le_bytes(next(4)?.try_into().ok()?);
+        let end = u32::from_le_bytes(next(4)?.try_into().ok()?);
+        ranges.push((start, end));
+    }
+
+    Some(Coverage { ranges })
+}
+
+fn write_cache(
+    cache_path: &Path,
+    font_path: &Path,
+    index: u32,
+    modified: SystemTime,
+    coverage: &Coverage,
+) -> std::io::Result<()> {
+    let font_path = font_path.as_os_str().as_encoded_bytes();
+    let mut data = Vec::with_capacity(MAGIC.len() + 36 + font_path.len() + coverage.ranges.len() * 8);
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&(font_path.len() as u64).to_le_bytes());
+    data.extend_from_slice(font_path);
+    data.extend_from_slice(&index.to_le_bytes());
+    data.extend_from_slice(&timestamp(modified));
+    data.extend_from_slice(&(coverage.ranges.len() as u64).to_le_bytes());
+
+    for (start, end) in &coverage.ranges {
+        data.extend_from_slice(&start.to_le_bytes());
+        data.extend_from_slice(&end.to_le_bytes());
+    }
+
+    fs::create_dir_all(cache_path.parent().unwrap())?;
+    fs::write(cache_path, data)
+}
+
+fn timestamp(time: SystemTime) -> [u8; 16] {
+    let nanos = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_nanos() as i128,
+        Err(err) => -(err.duration().as_nanos() as i128),
+    };
+
+    nanos.to_le_bytes()
+}
+
+fn cache_file(font_path: &Path, index: u32) -> Option<PathBuf> {
+    // FNV-1a, which unlike the std hasher is stable between builds
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in font_path
+        .as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .chain(&index.to_le_bytes())
+    {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    Some(
+        cache_dir()?
+            .join("hexv")
+            .join("fonts")
+            .join(format!("{:016x}.cov", hash)),
+    )
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let env_dir = |name: &str| std::env::var_os(name).filter(|dir| !dir.is_empty()).map(PathBuf::from);
+
+    if cfg!(windows) {
+        env_dir("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library").join("Caches"))
+    } else {
+        env_dir("XDG_CACHE_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".cache")))
+    }
+}
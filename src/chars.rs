@@ -0,0 +1,135 @@
+use unicode_width::UnicodeWidthChar;
+
+/// Returns whether the character changes the direction of the text around it, as used by Trojan Source attacks.
+pub(crate) fn is_bidi_control(char: char) -> bool {
+    matches!(
+        char,
+        '\u{061c}' | '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}'
+    )
+}
+
+/// Returns whether the character joins the parts of an emoji sequence, like the zero width joiner in 👨‍👩‍👧.
+pub(crate) fn is_emoji_joiner(char: char) -> bool {
+    matches!(char, '\u{200d}' | '\u{fe0f}')
+}
+
+/// Returns whether the character takes no visible space, so that it cannot be told apart from its absence.
+pub(crate) fn is_invisible(char: char) -> bool {
+    matches!(
+        char,
+        '\u{00ad}' // soft hyphen
+            | '\u{034f}' // combining grapheme joiner
+            | '\u{061c}' // arabic letter mark
+            | '\u{115f}'..='\u{1160}' // hangul fillers
+            | '\u{17b4}'..='\u{17b5}' // khmer inherent vowels
+            | '\u{180b}'..='\u{180f}' // mongolian variation selectors and vowel separator
+            | '\u{200b}'..='\u{200f}' // zero width space, non-joiner, joiner and direction marks
+            | '\u{202a}'..='\u{202e}' // bidirectional embeddings and overrides
+            | '\u{2060}'..='\u{2064}' // word joiner and invisible operators
+            | '\u{2066}'..='\u{206f}' // bidirectional isolates and deprecated format characters
+            | '\u{3164}' // hangul filler
+            | '\u{fe00}'..='\u{fe0f}' // variation selectors
+            | '\u{feff}' // zero width no-break space
+            | '\u{ffa0}' // halfwidth hangul filler
+            | '\u{1d173}'..='\u{1d17a}' // musical symbol formatting
+            | '\u{e0000}'..='\u{e007f}' // tags
+            | '\u{e0100}'..='\u{e01ef}' // variation selectors supplement
+    )
+}
+
+/// Returns the ASCII text that the character can be mistaken for, according to the Unicode confusables.
+pub(crate) fn ascii_confusable(char: char) -> Option<String> {
+    if char.is_ascii() {
+        return None;
+    }
+
+    let mut bytes = [0; 4];
+    let skeleton: String = unicode_security::skeleton(char.encode_utf8(&mut bytes)).collect();
+    Some(skeleton).filter(|skeleton| skeleton.is_ascii() && !skeleton.is_empty())
+}
+
+/// Returns the name of the HTML entity for the character, for the most common ones.
+pub(crate) fn html_entity(char: char) -> Option<&'static str> {
+    let name = match char {
+        '&' => "amp",
+        '<' => "lt",
+        '>' => "gt",
+        '"' => "quot",
+        '\'' => "apos",
+        '\u{a0}' => "nbsp",
+        '\u{ad}' => "shy",
+        '\u{2002}' => "ensp",
+        '\u{2003}' => "emsp",
+        '\u{2009}' => "thinsp",
+        '\u{200c}' => "zwnj",
+        '\u{200d}' => "zwj",
+        '\u{200e}' => "lrm",
+        '\u{200f}' => "rlm",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+/// Returns the number of columns that the character takes on a terminal, where wide characters like CJK take two and
+/// combining marks and control characters take none.
+pub(crate) fn column_width(char: char) -> usize {
+    char.width().unwrap_or(0)
+}
+
+/// Returns the column after the character is printed at `column`, where a tab moves on to the next tab stop and a new
+/// line or carriage return goes back to the start of the line.
+pub(crate) fn next_column(column: usize, char: char, tab_width: usize) -> usize {
+    match char {
+        '\t' => (column / tab_width + 1) * tab_width,
+        '\n' | '\r' => 0,
+        char => column + column_width(char),
+    }
+}
+
+/// Returns the visible symbol that stands for the whitespace character, where a new line is kept after its symbol.
+pub(crate) fn whitespace_symbol(char: char) -> Option<&'static str> {
+    let symbol = match char {
+        ' ' => "·",
+        '\t' => "→",
+        '\n' => "¶\n",
+        '\r' => "␍",
+        '\u{b}' => "␋",
+        '\u{c}' => "␌",
+        '\u{85}' => "␤",
+        '\u{a0}' => "⍽",
+        '\u{202f}' => "⍮",
+        '\u{3000}' => "□",
+        _ => return None,
+    };
+
+    Some(symbol)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn end_column(text: &str, tab_width: usize) -> usize {
+        text.chars()
+            .fold(0, |column, char| next_column(column, char, tab_width))
+    }
+
+    #[test]
+    fn wide_chars_before_tabs() {
+        assert_eq!(end_column("中文\t", 4), 8);
+        assert_eq!(end_column("中文\t", 8), 8);
+        assert_eq!(end_column("中\t", 4), 4);
+        assert_eq!(end_column("中\t", 8), 8);
+        assert_eq!(end_column("中文\t中\t", 4), 12);
+        assert_eq!(end_column("中文\t中\t", 8), 16);
+    }
+
+    #[test]
+    fn combining_marks_take_no_column() {
+        assert_eq!(column_width('\u{301}'), 0);
+        assert_eq!(end_column("中e\u{301}\t", 4), 4);
+        assert_eq!(end_column("中e\u{301}\t", 8), 8);
+        assert_eq!(end_column("中文e\u{301}\t", 4), 8);
+    }
+}
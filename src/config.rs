@@ -0,0 +1,155 @@
+use std::{collections::BTreeMap, ffi::OsString, io, path::PathBuf};
+
+use clap::{
+    error::{ContextKind, ContextValue, ErrorKind},
+    parser::ValueSource,
+    Arg, ArgAction, Command,
+};
+use serde::Deserialize;
+
+/// The defaults of the command line arguments, like `show-whitespace = true` or `fonts = ["Noto Sans", "Symbola"]`.
+#[derive(Default, Deserialize)]
+struct Config {
+    /// The default of --fontname.
+    #[serde(default)]
+    fonts: Vec<String>,
+    /// The defaults of the flags by their long names.
+    #[serde(flatten)]
+    flags: BTreeMap<String, bool>,
+}
+
+/// Sets the defaults of the arguments to the options of the config file, unless --no-config is given.
+///
+/// The config file is `hexv/config.toml` in the config directory, or the one of --config. Each flag of it can be turned
+/// off with --no-<flag>, and a conflicting flag of the command line overrides it. Returns an error if the file cannot be
+/// read, has options that are not flags of the command or sets a flag without the arguments it requires.
+pub fn apply(command: Command) -> io::Result<Command> {
+    // The arguments are only parsed for the config file here, so errors are reported by the final parsing
+    let matches = command.clone().ignore_errors(true).get_matches();
+
+    // The flag has no value if the arguments are invalid
+    if matches!(matches.try_get_one::<bool>("no-config"), Ok(Some(true))) {
+        return Ok(command);
+    }
+
+    let (path, required) = match matches.get_one::<PathBuf>("config") {
+        Some(path) => (path.clone(), true),
+        None => match config_dir() {
+            Some(dir) => (dir.join("hexv").join("config.toml"), false),
+            None => return Ok(command),
+        },
+    };
+
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if required || err.kind() != io::ErrorKind::NotFound => {
+            let message = format!("Could not read config file '{}': {}", path.display(), err);
+            return Err(io::Error::new(err.kind(), message));
+        }
+        Err(_) => return Ok(command),
+    };
+
+    let config: Config = toml::from_str(&text).map_err(|err| {
+        let message = format!("Could not parse config file '{}': {}", path.display(), err);
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    })?;
+
+    let mut command = command;
+    for name in config.flags.keys() {
+        let is_flag = command
+            .get_arguments()
+            .any(|arg| arg.get_id() == name.as_str() && matches!(arg.get_action(), ArgAction::SetTrue));
+
+        if !is_flag || name == "no-config" {
+            let message = format!("'{}' in config file '{}' is not a flag", name, path.display());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+
+        // The flags of the config file can be turned off with --no-<flag>
+        let negation = format!("no-{}", name);
+        if !command.get_arguments().any(|arg| arg.get_id() == negation.as_str()) {
+            command = command.arg(
+                Arg::new(negation.clone())
+                    .long(negation)
+                    .hide(true)
+                    .action(ArgAction::SetTrue),
+            );
+        }
+    }
+
+    // Parse the arguments again to know the negations
+    let matches = command.clone().ignore_errors(true).get_matches();
+    let given = |id: &str| matches!(matches.value_source(id), Some(ValueSource::CommandLine));
+
+    let mut defaults = Vec::new();
+    for (name, value) in config.flags {
+        // A flag of the command line overrides the defaults it conflicts with
+        let overridden = given(&format!("no-{}", name))
+            || conflicts(&command, &name)
+                .iter()
+                .any(|other| given(other.get_id().as_str()));
+
+        if value && !overridden {
+            defaults.push(name);
+        }
+    }
+
+    // Defaults are not checked for the arguments they require, so the command line is checked together with them
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let start = args.len().min(1);
+    let flags = defaults
+        .iter()
+        .filter(|name| !given(name))
+        .map(|name| OsString::from(format!("--{}", name)));
+    args.splice(start..start, flags);
+    if command.clone().try_get_matches().is_ok() {
+        if let Err(err) = command.clone().try_get_matches_from(&args) {
+            if err.kind() == ErrorKind::MissingRequiredArgument {
+                let missing = match err.get(ContextKind::InvalidArg) {
+                    Some(ContextValue::Strings(args)) => args.join(", "),
+                    _ => String::from("other arguments"),
+                };
+                let message = format!("The flags of config file '{}' require {}", path.display(), missing);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            }
+        }
+    }
+
+    for name in defaults {
+        command = command.mut_arg(name, |arg| arg.default_value("true"));
+    }
+
+    if !config.fonts.is_empty() {
+        command = command.mut_arg("fontname", |arg| arg.default_value(config.fonts.join(",")));
+    }
+
+    Ok(command)
+}
+
+/// Returns the arguments that conflict with the argument, in either direction.
+fn conflicts<'a>(command: &'a Command, id: &str) -> Vec<&'a Arg> {
+    let Some(arg) = command.get_arguments().find(|arg| arg.get_id() == id) else {
+        return Vec::new();
+    };
+
+    let mut conflicts = command.get_arg_conflicts_with(arg);
+    conflicts.extend(command.get_arguments().filter(|other| {
+        command
+            .get_arg_conflicts_with(other)
+            .iter()
+            .any(|conflict| conflict.get_id() == id)
+    }));
+    conflicts
+}
+
+fn config_dir() -> Option<PathBuf> {
+    let env_dir = |name: &str| std::env::var_os(name).filter(|dir| !dir.is_empty()).map(PathBuf::from);
+
+    if cfg!(windows) {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env_dir("XDG_CONFIG_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".config")))
+    }
+}
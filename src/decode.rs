@@ -0,0 +1,126 @@
+use std::fmt;
+
+use crate::{FormatterOptions, NumericBase};
+
+/// An escape sequence in the input of [`decode`] that could not be decoded.
+#[derive(Debug)]
+pub struct DecodeError {
+    /// The byte position of the escape sequence in the input.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Turns the escape sequences written by the formatter back into the original bytes.
+///
+/// Everything that is not an escape sequence is passed through as is.
+pub fn decode(input: &[u8], options: &FormatterOptions) -> Result<Vec<u8>, DecodeError> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut position = 0;
+    let mut space_glyph = [0; 4];
+    let space_glyph: &str = options.space_glyph.encode_utf8(&mut space_glyph);
+
+    while position < input.len() {
+        let rest = &input[position..];
+
+        if let Some(digits) = rest.strip_prefix(options.byte_prefix.as_bytes()) {
+            let byte = digits
+                .get(..2)
+                .and_then(|digits| std::str::from_utf8(digits).ok())
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| error(position, "Invalid hex escape"))?;
+
+            output.push(byte);
+            position += options.byte_prefix.len() + 2;
+        } else if let Some(digits) = rest.strip_prefix(options.decimal_prefix.as_bytes()) {
+            let byte = digits
+                .get(..3)
+                .and_then(|digits| std::str::from_utf8(digits).ok())
+                .and_then(|digits| digits.parse::<u8>().ok())
+                .ok_or_else(|| error(position, "Invalid decimal escape"))?;
+
+            output.push(byte);
+            position += options.decimal_prefix.len() + 3;
+        } else if let Some(braced) = rest.strip_prefix(options.unicode_prefix.as_bytes()) {
+            let radix = options.base.radix();
+            let end = braced.iter().position(|byte| *byte == b'}');
+            let char = end
+                .filter(|_| braced.first() == Some(&b'{'))
+                .and_then(|end| std::str::from_utf8(&braced[1..end]).ok())
+                .and_then(|digits| u32::from_str_radix(digits, radix).ok())
+                .and_then(char::from_u32)
+                .ok_or_else(|| error(position, "Invalid unicode escape"))?;
+
+            output.extend_from_slice(char.encode_utf8(&mut [0; 4]).as_bytes());
+            position += options.unicode_prefix.len() + end.unwrap() + 1;
+        } else if let Some(byte) = rest
+            .strip_prefix(options.octal_prefix.as_bytes())
+            .filter(|_| options.base == NumericBase::Octal)
+            .and_then(octal_byte)
+        {
+            // The default octal prefix is a plain backslash, so other escapes with it are not octal values
+            output.push(byte);
+            position += options.octal_prefix.len() + 3;
+        } else if let Some(digits) = rest
+            .strip_prefix(options.binary_prefix.as_bytes())
+            .filter(|_| options.base == NumericBase::Binary)
+        {
+            let (byte, len) = binary_byte(digits).ok_or_else(|| error(position, "Invalid binary escape"))?;
+            output.push(byte);
+            position += options.binary_prefix.len() + len;
+        } else if let Some(byte) = short_escape(rest) {
+            output.push(byte);
+            position += 2;
+        } else if options.space_as_circle && rest.starts_with(space_glyph.as_bytes()) {
+            output.push(b' ');
+            position += space_glyph.len();
+        } else {
+            output.push(rest[0]);
+            position += 1;
+        }
+    }
+
+    Ok(output)
+}
+
+fn octal_byte(digits: &[u8]) -> Option<u8> {
+    let digits = std::str::from_utf8(digits.get(..3)?).ok()?;
+    u8::from_str_radix(digits, 8).ok()
+}
+
+/// Parses 8 binary digits, which may be grouped into nibbles, and returns the byte and the length of the digits.
+fn binary_byte(digits: &[u8]) -> Option<(u8, usize)> {
+    let (bits, len) = match digits.get(4) {
+        Some(b'_') => ([digits.get(..4)?, digits.get(5..9)?].concat(), 9),
+        _ => (digits.get(..8)?.to_vec(), 8),
+    };
+
+    let byte = u8::from_str_radix(std::str::from_utf8(&bits).ok()?, 2).ok()?;
+    Some((byte, len))
+}
+
+fn short_escape(input: &[u8]) -> Option<u8> {
+    match input.get(..2)? {
+        b"\\n" => Some(b'\n'),
+        b"\\r" => Some(b'\r'),
+        b"\\t" => Some(b'\t'),
+        b"\\f" => Some(b'\x0c'),
+        b"\\v" => Some(b'\x0b'),
+        b"\\0" => Some(b'\0'),
+        _ => None,
+    }
+}
+
+fn error(position: usize, message: &str) -> DecodeError {
+    DecodeError {
+        position,
+        message: message.to_string(),
+    }
+}
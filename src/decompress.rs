@@ -0,0 +1,51 @@
+use std::io::{self, BufRead};
+
+/// How the compression of the input is decided with `--decompress`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decompress {
+    /// By the extension of the file, or else by the magic bytes at its start.
+    Auto,
+    Gzip,
+    Zstd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+impl Decompress {
+    /// Returns the compression of the input at `path`, which starts with `start`, or `None` if it is not compressed.
+    pub fn compression(self, path: &str, start: &[u8]) -> Option<Compression> {
+        match self {
+            Decompress::Gzip => Some(Compression::Gzip),
+            Decompress::Zstd => Some(Compression::Zstd),
+            _ if path.ends_with(".gz") || path.ends_with(".gzip") => Some(Compression::Gzip),
+            _ if path.ends_with(".zst") || path.ends_with(".zstd") => Some(Compression::Zstd),
+            _ if start.starts_with(GZIP_MAGIC) => Some(Compression::Gzip),
+            _ if start.starts_with(ZSTD_MAGIC) => Some(Compression::Zstd),
+            Decompress::Auto => None,
+        }
+    }
+}
+
+/// Returns a reader of the decompressed input, where concatenated gzip members are read one after the other.
+#[cfg(feature = "compression")]
+pub fn decoder(reader: Box<dyn BufRead>, compression: Compression) -> io::Result<Box<dyn BufRead>> {
+    Ok(match compression {
+        Compression::Gzip => Box::new(io::BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        Compression::Zstd => Box::new(io::BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+    })
+}
+
+#[cfg(not(feature = "compression"))]
+pub fn decoder(_reader: Box<dyn BufRead>, _compression: Compression) -> io::Result<Box<dyn BufRead>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "hexv was built without the compression feature",
+    ))
+}
@@ -0,0 +1,32 @@
+use hexv::Encoding;
+
+/// The number of bytes at the start of an input that its encoding is detected from.
+pub const SAMPLE_LEN: u64 = 64 * 1024;
+
+/// The most likely charset of an input.
+pub struct Detection {
+    /// The name of the charset, like `ISO-8859-1`.
+    pub charset: String,
+    /// How likely the input has the charset, from 0 to 1.
+    pub confidence: f32,
+}
+
+impl Detection {
+    pub fn new(sample: &[u8]) -> Self {
+        let (charset, confidence, _) = chardet::detect(sample);
+        Self { charset, confidence }
+    }
+
+    /// Returns the encoding to read the input with, or `None` if the charset is not supported.
+    pub fn encoding(&self) -> Option<Encoding> {
+        match self.charset.to_lowercase().as_str() {
+            // ASCII text is also valid UTF-8
+            "ascii" | "utf-8" => Some(Encoding::Utf8),
+            "utf-16le" => Some(Encoding::Utf16Le),
+            "utf-16be" => Some(Encoding::Utf16Be),
+            "iso-8859-1" => Some(Encoding::Latin1),
+            "windows-1252" => Some(Encoding::Windows1252),
+            _ => None,
+        }
+    }
+}
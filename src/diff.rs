@@ -0,0 +1,128 @@
+use std::io::{self, Write};
+
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+
+/// The original bytes of a character and the text that the formatter writes for it.
+pub type FormattedChar = (Vec<u8>, Vec<u8>);
+
+/// Writes each line in which the characters differ, like `git diff --word-diff`, and returns whether any do.
+///
+/// The characters are compared by their original bytes, so characters that look alike still differ. The removed
+/// characters are marked with `[-…-]` and the added ones with `{+…+}`, or in red and green with `color`.
+pub fn write_diff(
+    output: &mut dyn Write,
+    old: &[FormattedChar],
+    new: &[FormattedChar],
+    color: bool,
+) -> io::Result<bool> {
+    let (removed, added) = match color {
+        true => (("\x1b[31m", "\x1b[0m"), ("\x1b[32m", "\x1b[0m")),
+        false => (("[-", "-]"), ("{+", "+}")),
+    };
+
+    let old_bytes: Vec<&[u8]> = old.iter().map(|(bytes, _)| bytes.as_slice()).collect();
+    let new_bytes: Vec<&[u8]> = new.iter().map(|(bytes, _)| bytes.as_slice()).collect();
+    let ops = capture_diff_slices(Algorithm::Myers, &old_bytes, &new_bytes);
+    let mut line = Line::default();
+    let mut differs = false;
+
+    for op in ops {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+
+        if tag != DiffTag::Equal {
+            differs = true;
+            line.changed = true;
+            line.old_breaks += line.push_changed(&old[old_range], removed);
+            line.new_breaks += line.push_changed(&new[new_range], added);
+            continue;
+        }
+
+        for (_, text) in &old[old_range] {
+            line.text.extend_from_slice(text);
+
+            // A new line that is in both inputs ends the line in both
+            if text.ends_with(b"\n") {
+                line.write(output)?;
+                line.next();
+            }
+        }
+    }
+
+    if !line.text.is_empty() {
+        line.text.push(b'\n');
+        line.write(output)?;
+    }
+
+    Ok(differs)
+}
+
+/// The formatted text of the current line, which is only written if it has differences.
+struct Line {
+    text: Vec<u8>,
+    changed: bool,
+    old_number: usize,
+    new_number: usize,
+    /// The new lines in the line that were removed, which only count for the old input.
+    old_breaks: usize,
+    /// The new lines in the line that were added, which only count for the new input.
+    new_breaks: usize,
+}
+
+impl Default for Line {
+    fn default() -> Self {
+        Self {
+            text: Vec::new(),
+            changed: false,
+            old_number: 1,
+            new_number: 1,
+            old_breaks: 0,
+            new_breaks: 0,
+        }
+    }
+}
+
+impl Line {
+    /// Adds the removed or added characters between the markers, and returns the number of new lines among them.
+    ///
+    /// The new lines are shown as `\n`, so the line goes on.
+    fn push_changed(&mut self, chars: &[FormattedChar], (start, end): (&str, &str)) -> usize {
+        if chars.is_empty() {
+            return 0;
+        }
+
+        let mut breaks = 0;
+        self.text.extend_from_slice(start.as_bytes());
+
+        for (_, text) in chars {
+            match text.strip_suffix(b"\n") {
+                Some(text) => {
+                    let text: &[u8] = if text.is_empty() { b"\\n" } else { text };
+                    self.text.extend_from_slice(text);
+                    breaks += 1;
+                }
+                None => self.text.extend_from_slice(text),
+            }
+        }
+
+        self.text.extend_from_slice(end.as_bytes());
+        breaks
+    }
+
+    fn write(&self, output: &mut dyn Write) -> io::Result<()> {
+        if self.changed {
+            write!(output, "@@ -{} +{} @@ ", self.old_number, self.new_number)?;
+            output.write_all(&self.text)?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts the next line after a new line in both inputs.
+    fn next(&mut self) {
+        *self = Self {
+            old_number: self.old_number + 1 + self.old_breaks,
+            new_number: self.new_number + 1 + self.new_breaks,
+            ..Self::default()
+        };
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::{self, Write};
+
+/// The text encoding of the whole input with `--encode`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextEncoding {
+    Base64,
+    Base32,
+    Hex,
+}
+
+impl TextEncoding {
+    fn encoding(self) -> data_encoding::Encoding {
+        match self {
+            TextEncoding::Base64 => data_encoding::BASE64,
+            TextEncoding::Base32 => data_encoding::BASE32,
+            TextEncoding::Hex => data_encoding::HEXLOWER,
+        }
+    }
+
+    /// Returns the number of bytes that are encoded without padding.
+    fn block_len(self) -> usize {
+        match self {
+            TextEncoding::Base64 => 3,
+            TextEncoding::Base32 => 5,
+            TextEncoding::Hex => 1,
+        }
+    }
+}
+
+/// Encodes the input in parts, where the bytes that do not fill a block are kept until the next part.
+pub struct Encoder {
+    encoding: TextEncoding,
+    pending: Vec<u8>,
+    /// Break the lines after this many characters.
+    wrap: Option<usize>,
+    column: usize,
+}
+
+impl Encoder {
+    pub fn new(encoding: TextEncoding, wrap: Option<usize>) -> Self {
+        Self {
+            encoding,
+            pending: Vec::new(),
+            wrap,
+            column: 0,
+        }
+    }
+
+    pub fn write(&mut self, output: &mut dyn Write, bytes: &[u8]) -> io::Result<()> {
+        self.pending.extend_from_slice(bytes);
+
+        let complete = self.pending.len() - self.pending.len() % self.encoding.block_len();
+        let text = self.encoding.encoding().encode(&self.pending[..complete]);
+        self.pending.drain(..complete);
+        self.write_text(output, &text)
+    }
+
+    /// Writes the rest of the input with padding, and ends the line if anything was written.
+    pub fn finish(&mut self, output: &mut dyn Write) -> io::Result<()> {
+        let text = self.encoding.encoding().encode(&self.pending);
+        self.pending.clear();
+        self.write_text(output, &text)?;
+
+        if self.column > 0 {
+            writeln!(output)?;
+            self.column = 0;
+        }
+
+        Ok(())
+    }
+
+    fn write_text(&mut self, output: &mut dyn Write, mut text: &str) -> io::Result<()> {
+        let Some(wrap) = self.wrap else {
+            self.column += text.len();
+            return output.write_all(text.as_bytes());
+        };
+
+        // The encoded text is ASCII, so each byte takes one column
+        while !text.is_empty() {
+            let (line, rest) = text.split_at(text.len().min(wrap - self.column));
+            output.write_all(line.as_bytes())?;
+            self.column += line.len();
+            text = rest;
+
+            if self.column == wrap {
+                writeln!(output)?;
+                self.column = 0;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Decodes base64 text, where whitespace like the breaks of wrapped lines is ignored.
+pub fn decode_base64(text: &[u8]) -> Result<Vec<u8>, data_encoding::DecodeError> {
+    let mut specification = data_encoding::BASE64.specification();
+    specification.ignore.push_str(" \t\r\n");
+    specification.encoding().unwrap().decode(text)
+}
+
+/// Parses hex text like `48 65 6c 6c 6f` or `0x48,0x65` into the bytes, where each token between whitespace or commas
+/// has pairs of hex digits.
+pub fn decode_hex_tokens(text: &[u8]) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+
+    for token in text
+        .split(|byte| byte.is_ascii_whitespace() || *byte == b',')
+        .filter(|token| !token.is_empty())
+    {
+        let digits = token
+            .strip_prefix(b"0x")
+            .or_else(|| token.strip_prefix(b"0X"))
+            .unwrap_or(token);
+
+        if digits.len() % 2 != 0 {
+            return Err(format!(
+                "odd number of hex digits in '{}'",
+                String::from_utf8_lossy(token)
+            ));
+        }
+
+        match data_encoding::HEXLOWER_PERMISSIVE.decode(digits) {
+            Ok(decoded) if !decoded.is_empty() => bytes.extend(decoded),
+            _ => return Err(format!("invalid hex token '{}'", String::from_utf8_lossy(token))),
+        }
+    }
+
+    Ok(bytes)
+}
@@ -0,0 +1,264 @@
+use bstr::ByteSlice;
+
+/// The text encoding of the input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1, where each byte is the character with the same codepoint.
+    Latin1,
+    Windows1252,
+    /// UTF-16 if the input starts with its byte order mark, otherwise UTF-8.
+    Auto,
+}
+
+/// A byte order mark at the start of the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bom {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Utf32Le,
+    Utf32Be,
+}
+
+impl Bom {
+    // UTF-32LE comes first, as its byte order mark starts with the one of UTF-16LE
+    const ALL: [Bom; 5] = [Bom::Utf32Le, Bom::Utf32Be, Bom::Utf8, Bom::Utf16Le, Bom::Utf16Be];
+
+    pub fn bytes(self) -> &'static [u8] {
+        match self {
+            Bom::Utf8 => &[0xef, 0xbb, 0xbf],
+            Bom::Utf16Le => &[0xff, 0xfe],
+            Bom::Utf16Be => &[0xfe, 0xff],
+            Bom::Utf32Le => &[0xff, 0xfe, 0x00, 0x00],
+            Bom::Utf32Be => &[0x00, 0x00, 0xfe, 0xff],
+        }
+    }
+
+    /// Returns the byte order mark at the start of the input.
+    pub(crate) fn detect(start: &[u8]) -> Option<Bom> {
+        Bom::ALL.into_iter().find(|bom| start.starts_with(bom.bytes()))
+    }
+
+    /// Returns whether the start of the input is too short to tell which byte order mark it has.
+    pub(crate) fn is_incomplete(start: &[u8]) -> bool {
+        Bom::ALL
+            .into_iter()
+            .any(|bom| start.len() < bom.bytes().len() && bom.bytes().starts_with(start))
+    }
+}
+
+impl std::fmt::Display for Bom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Bom::Utf8 => "UTF-8",
+            Bom::Utf16Le => "UTF-16LE",
+            Bom::Utf16Be => "UTF-16BE",
+            Bom::Utf32Le => "UTF-32LE",
+            Bom::Utf32Be => "UTF-32BE",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl Encoding {
+    /// Picks the encoding for [`Encoding::Auto`] by the byte order mark of the input.
+    pub(crate) fn from_bom(bom: Option<Bom>) -> Encoding {
+        match bom {
+            Some(Bom::Utf16Le) => Encoding::Utf16Le,
+            Some(Bom::Utf16Be) => Encoding::Utf16Be,
+            _ => Encoding::Utf8,
+        }
+    }
+
+    /// Returns the length of the incomplete character at the end of the buffer, which may be completed by more bytes.
+    pub(crate) fn incomplete_suffix(self, buffer: &[u8]) -> usize {
+        match self {
+            Encoding::Utf8 | Encoding::Auto => incomplete_utf8_suffix(buffer),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let odd = buffer.len() % 2;
+                let end = buffer.len() - odd;
+
+                match end.checked_sub(2).map(|start| self.unit(buffer, start)) {
+                    Some(unit) if is_high_surrogate(unit) => odd + 2,
+                    _ => odd,
+                }
+            }
+            Encoding::Latin1 | Encoding::Windows1252 => 0,
+        }
+    }
+
+    /// Returns the length of the carriage return at the end of the buffer, or 0 if it does not end with one.
+    pub(crate) fn trailing_carriage_return(self, buffer: &[u8]) -> usize {
+        match self {
+            Encoding::Utf16Le if buffer.ends_with(b"\r\0") => 2,
+            Encoding::Utf16Be if buffer.ends_with(b"\0\r") => 2,
+            Encoding::Utf16Le | Encoding::Utf16Be => 0,
+            _ if buffer.ends_with(b"\r") => 1,
+            _ => 0,
+        }
+    }
+
+    /// Returns the position after the last ASCII character in the buffer that matches the predicate.
+    pub(crate) fn last_ascii_end(self, buffer: &[u8], predicate: impl Fn(u8) -> bool) -> Option<usize> {
+        match self {
+            Encoding::Utf8 | Encoding::Latin1 | Encoding::Windows1252 | Encoding::Auto => buffer
+                .iter()
+                .rposition(|byte| byte.is_ascii() && predicate(*byte))
+                .map(|index| index + 1),
+            Encoding::Utf16Le | Encoding::Utf16Be => (0..buffer.len().saturating_sub(1))
+                .step_by(2)
+                .rev()
+                .find(|index| {
+                    let unit = self.unit(buffer, *index);
+                    unit < 0x80 && predicate(unit as u8)
+                })
+                .map(|index| index + 2),
+        }
+    }
+
+    /// Calls `f` with the bytes of each character in the buffer, and the character if the bytes are valid.
+    pub(crate) fn for_each_char<'b>(self, buffer: &'b [u8], mut f: impl FnMut(&'b [u8], Option<char>)) {
+        match self {
+            Encoding::Utf8 | Encoding::Auto => {
+                for (start, end, char) in buffer.char_indices() {
+                    let bytes = &buffer[start..end];
+                    let is_valid = char != char::REPLACEMENT_CHARACTER || bytes == "\u{fffd}".as_bytes();
+                    f(bytes, Some(char).filter(|_| is_valid));
+                }
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let mut index = 0;
+
+                while index + 2 <= buffer.len() {
+                    let unit = self.unit(buffer, index);
+
+                    if is_high_surrogate(unit) && index + 4 <= buffer.len() {
+                        let low = self.unit(buffer, index + 2);
+
+                        if (0xdc00..0xe000).contains(&low) {
+                            let codepoint = 0x10000 + ((u32::from(unit) - 0xd800) << 10) + (u32::from(low) - 0xdc00);
+                            f(&buffer[index..index + 4], char::from_u32(codepoint));
+                            index += 4;
+                            continue;
+                        }
+                    }
+
+                    // Unpaired surrogates are not characters
+                    f(&buffer[index..index + 2], char::from_u32(u32::from(unit)));
+                    index += 2;
+                }
+
+                if index < buffer.len() {
+                    f(&buffer[index..], None);
+                }
+            }
+            Encoding::Latin1 => {
+                for byte in buffer.chunks(1) {
+                    f(byte, Some(char::from(byte[0])));
+                }
+            }
+            Encoding::Windows1252 => {
+                for byte in buffer.chunks(1) {
+                    let char = match byte[0] {
+                        byte @ 0x80..=0x9f => WINDOWS_1252[usize::from(byte - 0x80)],
+                        byte => Some(char::from(byte)),
+                    };
+                    f(byte, char);
+                }
+            }
+        }
+    }
+
+    /// Returns the position after the first newline that ends at or after `from`.
+    pub(crate) fn find_line_end(self, buffer: &[u8], from: usize) -> Option<usize> {
+        match self {
+            Encoding::Utf8 | Encoding::Latin1 | Encoding::Windows1252 | Encoding::Auto => {
+                buffer.get(from..)?.find_byte(b'\n').map(|index| from + index + 1)
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => (from.next_multiple_of(2)..buffer.len().saturating_sub(1))
+                .step_by(2)
+                .find(|index| self.unit(buffer, *index) == u16::from(b'\n'))
+                .map(|index| index + 2),
+        }
+    }
+
+    fn unit(self, buffer: &[u8], index: usize) -> u16 {
+        let bytes = [buffer[index], buffer[index + 1]];
+
+        if self == Encoding::Utf16Be {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+}
+
+/// The characters of the bytes from 0x80 to 0x9f in Windows-1252, where the others match Latin-1.
+const WINDOWS_1252: [Option<char>; 32] = [
+    Some('€'),
+    None,
+    Some('‚'),
+    Some('ƒ'),
+    Some('„'),
+    Some('…'),
+    Some('†'),
+    Some('‡'),
+    Some('ˆ'),
+    Some('‰'),
+    Some('Š'),
+    Some('‹'),
+    Some('Œ'),
+    None,
+    Some('Ž'),
+    None,
+    None,
+    Some('\u{2018}'),
+    Some('\u{2019}'),
+    Some('\u{201c}'),
+    Some('\u{201d}'),
+    Some('•'),
+    Some('–'),
+    Some('—'),
+    Some('˜'),
+    Some('™'),
+    Some('š'),
+    Some('›'),
+    Some('œ'),
+    None,
+    Some('ž'),
+    Some('Ÿ'),
+];
+
+fn is_high_surrogate(unit: u16) -> bool {
+    (0xd800..0xdc00).contains(&unit)
+}
+
+/// Returns the length of the incomplete UTF-8 sequence at the end of the buffer, which may be completed by more bytes.
+fn incomplete_utf8_suffix(buffer: &[u8]) -> usize {
+    for len in 1..=buffer.len().min(3) {
+        let start = buffer.len() - len;
+        let byte = buffer[start];
+
+        // Skip continuation bytes until the start of the sequence
+        if byte & 0xc0 == 0x80 {
+            continue;
+        }
+
+        let width = match byte {
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => 0,
+        };
+
+        let is_prefix = std::str::from_utf8(&buffer[start..]).is_err_and(|err| err.error_len().is_none());
+        return if width > len && is_prefix { len } else { 0 };
+    }
+
+    0
+}
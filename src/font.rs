@@ -0,0 +1,231 @@
+use std::{io, ops::Deref, path::Path};
+
+use ab_glyph::Font;
+
+use crate::cache::{load_cached, Coverage};
+
+#[allow(clippy::large_enum_variant)]
+pub enum FontCow<'a> {
+    FontVec(ab_glyph::FontVec),
+    FontRef(ab_glyph::FontRef<'a>),
+    /// Only the characters covered by a font, as read from the cache.
+    Coverage(Coverage),
+}
+
+impl FontCow<'_> {
+    /// Returns the glyph id of the character, which is 0 if the font has no glyph for it.
+    ///
+    /// The cached coverage of a font does not know the actual ids, so it returns 1 for covered characters.
+    pub fn glyph_id(&self, c: char) -> ab_glyph::GlyphId {
+        match self {
+            FontCow::FontVec(f) => f.glyph_id(c),
+            FontCow::FontRef(f) => f.glyph_id(c),
+            FontCow::Coverage(coverage) => ab_glyph::GlyphId(coverage.contains(c).into()),
+        }
+    }
+
+    /// Returns the number of glyphs of the font, which is unknown for the cached coverage.
+    pub fn glyph_count(&self) -> Option<usize> {
+        match self {
+            FontCow::FontVec(f) => Some(f.glyph_count()),
+            FontCow::FontRef(f) => Some(f.glyph_count()),
+            FontCow::Coverage(_) => None,
+        }
+    }
+
+    pub fn has_glyph(&self, c: char) -> bool {
+        match self {
+            FontCow::Coverage(coverage) => coverage.contains(c),
+            _ => self.glyph_id(c).0 != 0,
+        }
+    }
+}
+
+/// Returns whether any of the fonts has a glyph for the character.
+pub fn is_char_in_fonts(fonts: &[FontCow], char: char) -> bool {
+    find_font_for_char(fonts, char).is_some()
+}
+
+/// Returns the index of the first font that has a glyph for the character.
+pub fn find_font_for_char(fonts: &[FontCow], char: char) -> Option<usize> {
+    fonts.iter().position(|font| font.has_glyph(char))
+}
+
+/// The face to pick from a font family.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FontProperties {
+    pub weight: fontdb::Weight,
+    pub style: fontdb::Style,
+    pub stretch: fontdb::Stretch,
+}
+
+/// Looks up the font family in the database, returns its source and the index of the face in it, or `None` if it is
+/// not installed.
+pub fn get_font_source(
+    font_db: &fontdb::Database,
+    fontname: &str,
+    properties: &FontProperties,
+) -> Option<(fontdb::Source, u32)> {
+    let query = fontdb::Query {
+        families: &[fontdb::Family::Name(fontname)],
+        weight: properties.weight,
+        style: properties.style,
+        stretch: properties.stretch,
+    };
+
+    let id = font_db.query(&query)?;
+    let (src, index) = font_db.face_source(id).unwrap();
+    Some((src, index))
+}
+
+/// Returns the sans-serif font family, or the first installed sans font if it is not installed.
+pub fn default_font_family(font_db: &fontdb::Database) -> Option<String> {
+    let query = fontdb::Query {
+        families: &[fontdb::Family::SansSerif],
+        ..fontdb::Query::default()
+    };
+
+    if font_db.query(&query).is_some() {
+        return Some(font_db.family_name(&fontdb::Family::SansSerif).to_string());
+    }
+
+    let mut families: Vec<&str> = font_db
+        .faces()
+        .flat_map(|face| face.families.iter().map(|(family, _)| family.as_str()))
+        .collect();
+    families.sort_unstable();
+
+    families
+        .iter()
+        .find(|family| family.contains("Sans") && !family.contains("Mono"))
+        .or(families.first())
+        .map(|family| family.to_string())
+}
+
+/// How a font name is matched with the names of the installed font families.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FontMatch {
+    #[default]
+    Exact,
+    /// The family name contains the font name, ignoring the case.
+    Substring,
+    /// The family name that is most similar to the font name, ignoring the case.
+    Fuzzy,
+}
+
+/// The lowest Jaro-Winkler similarity of a family name to be matched with [`FontMatch::Fuzzy`].
+const FUZZY_THRESHOLD: f64 = 0.7;
+
+/// Returns the name of the installed font family that matches `fontname`, or `None` if there is none.
+///
+/// An installed family with exactly the font name is always preferred. Of the families that contain the font name, the
+/// shortest one is the best match, so `noto` matches `Noto Sans` before `Noto Sans Mono`.
+pub fn match_font_family(font_db: &fontdb::Database, fontname: &str, font_match: FontMatch) -> Option<String> {
+    let families = family_names(font_db);
+
+    if font_match == FontMatch::Exact || families.contains(&fontname) {
+        return Some(fontname.to_string());
+    }
+
+    let fontname = fontname.to_lowercase();
+    let family = match font_match {
+        FontMatch::Exact => unreachable!(),
+        FontMatch::Substring => families
+            .into_iter()
+            .filter(|family| family.to_lowercase().contains(&fontname))
+            .min_by_key(|family| family.len()),
+        FontMatch::Fuzzy => families
+            .into_iter()
+            .map(|family| (strsim::jaro_winkler(&fontname, &family.to_lowercase()), family))
+            .filter(|(score, _)| *score >= FUZZY_THRESHOLD)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, family)| family),
+    };
+
+    family.map(String::from)
+}
+
+/// Returns the names of the installed font families, sorted and without duplicates.
+fn family_names(font_db: &fontdb::Database) -> Vec<&str> {
+    let mut families: Vec<&str> = font_db
+        .faces()
+        .flat_map(|face| face.families.iter().map(|(family, _)| family.as_str()))
+        .collect();
+    families.sort_unstable();
+    families.dedup();
+    families
+}
+
+/// Returns up to `count` installed font families, ordered by their edit distance to `fontname`.
+pub fn similar_font_names(font_db: &fontdb::Database, fontname: &str, count: usize) -> Vec<String> {
+    let fontname = fontname.to_lowercase();
+    let mut families: Vec<(usize, &str)> = family_names(font_db)
+        .into_iter()
+        .map(|family| (strsim::levenshtein(&fontname, &family.to_lowercase()), family))
+        .collect();
+    families.sort();
+
+    families
+        .into_iter()
+        .take(count)
+        .map(|(_, family)| family.to_string())
+        .collect()
+}
+
+/// The signature at the start of WOFF2 fonts.
+const WOFF2_MAGIC: &[u8] = b"wOF2";
+
+/// Parses the face at the index of a font file, which is first decompressed if it is a WOFF2 font.
+pub(crate) fn parse_font(data: Vec<u8>, index: u32) -> io::Result<ab_glyph::FontVec> {
+    let data = if data.starts_with(WOFF2_MAGIC) {
+        wuff::decompress_woff2(&data).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid WOFF2 font"))?
+    } else {
+        data
+    };
+
+    ab_glyph::FontVec::try_from_vec_and_index(data, index)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Loads the face at the index of the source, like the index of [`get_font_source`], where an invalid font is an error
+/// of kind [`io::ErrorKind::InvalidData`].
+pub fn load_font(font_source: &fontdb::Source, index: u32) -> io::Result<FontCow<'_>> {
+    match font_source {
+        // WOFF2 fonts cannot be borrowed, as they have to be decompressed
+        fontdb::Source::Binary(bin) | fontdb::Source::SharedFile(_, bin)
+            if bin.deref().as_ref().starts_with(WOFF2_MAGIC) =>
+        {
+            parse_font(bin.deref().as_ref().to_vec(), index).map(FontCow::FontVec)
+        }
+        fontdb::Source::Binary(bin) | fontdb::Source::SharedFile(_, bin) => {
+            ab_glyph::FontRef::try_from_slice_and_index(bin.deref().as_ref(), index)
+                .map(FontCow::FontRef)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        }
+        fontdb::Source::File(path) => parse_font(std::fs::read(path)?, index).map(FontCow::FontVec),
+    }
+}
+
+/// Like [`load_font`], but uses the cached coverage of font files.
+pub fn load_font_cached(font_source: &fontdb::Source, index: u32) -> io::Result<FontCow<'_>> {
+    match font_source {
+        fontdb::Source::File(path) => match load_cached(path, index) {
+            Some(font) => Ok(font),
+            None => load_font(font_source, index),
+        },
+        _ => load_font(font_source, index),
+    }
+}
+
+/// Loads the first face of a font file, where an invalid font is an error of kind [`io::ErrorKind::InvalidData`].
+pub fn load_font_file(path: &Path) -> io::Result<FontCow<'static>> {
+    parse_font(std::fs::read(path)?, 0).map(FontCow::FontVec)
+}
+
+/// Like [`load_font_file`], but uses the cached coverage of the font file.
+pub fn load_font_file_cached(path: &Path) -> io::Result<FontCow<'static>> {
+    match load_cached(path, 0) {
+        Some(font) => Ok(font),
+        None => load_font_file(path),
+    }
+}
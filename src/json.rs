@@ -0,0 +1,15 @@
+use serde::Serialize;
+
+/// A character of the input, as written with [`FormatterOptions::json`](crate::FormatterOptions::json).
+#[derive(Serialize)]
+pub(crate) struct CharRecord<'a> {
+    /// The byte position of the character in the input.
+    pub offset: usize,
+    pub bytes: &'a [u8],
+    /// The character, or `None` if the bytes are invalid.
+    pub char: Option<char>,
+    pub codepoint: Option<u32>,
+    /// Whether the character is printed as is.
+    pub rendered: bool,
+    pub escaped_reason: Option<&'static str>,
+}
@@ -0,0 +1,2240 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    io::{self, Write},
+    ops::RangeInclusive,
+};
+
+use output::Output;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+mod cache;
+mod chars;
+mod decode;
+mod encoding;
+mod font;
+mod json;
+mod output;
+mod stats;
+
+pub use cache::Coverage;
+pub use decode::{decode, DecodeError};
+pub use encoding::{Bom, Encoding};
+pub use font::{
+    default_font_family, find_font_for_char, get_font_source, is_char_in_fonts, load_font, load_font_cached,
+    load_font_file, load_font_file_cached, match_font_family, similar_font_names, FontCow, FontMatch, FontProperties,
+};
+pub use stats::ByteStats;
+
+/// The base in which the values of bytes and characters are printed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NumericBase {
+    #[default]
+    Hex,
+    Decimal,
+    Octal,
+    Binary,
+}
+
+impl NumericBase {
+    pub fn radix(self) -> u32 {
+        match self {
+            NumericBase::Hex => 16,
+            NumericBase::Decimal => 10,
+            NumericBase::Octal => 8,
+            NumericBase::Binary => 2,
+        }
+    }
+}
+
+/// The syntax of escaped characters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EscapeStyle {
+    /// Unicode values like `\u{1f600}` and byte values like `\xff`, with the configured prefixes.
+    #[default]
+    Default,
+    /// HTML entities like `&#x1f600;`, where `<`, `>`, `&` and `"` are always escaped.
+    Html,
+    /// Rust string literal escapes like `\u{1f600}` and `\x7f`, where `"` and `\` are always escaped.
+    ///
+    /// String literals have no escapes for invalid bytes, so they are written like `\xff` in byte string literals
+    /// `b"…"`, as are the bytes of [`Formatter::dump_bytes`].
+    Rust,
+    /// C string literal escapes like `\U0001F600` and `\377`, where `"` and `\` are always escaped.
+    C,
+}
+
+/// Settings that decide how each character of the input is printed.
+#[derive(Clone, Debug)]
+pub struct FormatterOptions {
+    pub as_bytes: bool,
+    pub all_as_hex: bool,
+    pub base: NumericBase,
+    pub newline_escaped: bool,
+    pub newline_as_hex: bool,
+    pub carriage_return_as_hex: bool,
+    /// Print a carriage return followed by a new line as one `⏎`, unless either is printed as a value.
+    pub crlf: bool,
+    pub tab_as_hex: bool,
+    pub formfeed_as_hex: bool,
+    pub vtab_as_hex: bool,
+    /// Print a tab as `→`, padded with spaces to the next tab stop.
+    pub tab_arrow: bool,
+    /// The number of columns between tab stops.
+    pub tab_width: usize,
+    pub space_as_circle: bool,
+    /// The symbol for a space with `space_as_circle`.
+    pub space_glyph: char,
+    pub space_as_hex: bool,
+    /// Print whitespace characters as visible symbols, like `·` for a space and `→` for a tab.
+    pub show_whitespace: bool,
+    pub byte_prefix: String,
+    pub decimal_prefix: String,
+    pub octal_prefix: String,
+    pub binary_prefix: String,
+    /// Separate the nibbles of binary byte values with an underscore.
+    pub group_nibbles: bool,
+    /// Write the hex digits of byte values in uppercase.
+    pub uppercase: bool,
+    /// Written between byte values that are printed directly one after the other.
+    pub byte_separator: String,
+    /// The number of byte values in each group between separators, and in the rows of `offsets`.
+    pub byte_group: usize,
+    /// Write the byte values of each group in reverse, like the little-endian numbers of `xxd -e`.
+    pub reverse_groups: bool,
+    pub escape_style: EscapeStyle,
+    /// Prefer named HTML entities like `&nbsp;` with [`EscapeStyle::Html`].
+    pub html_named: bool,
+    pub unicode_prefix: String,
+    /// Prefix each row of dumped bytes with its offset.
+    pub offsets: bool,
+    /// The number of bytes per row when dumping bytes with offsets.
+    pub columns: usize,
+    /// Prefix each escaped character with the offset of its first byte in the input, like `@12:\xff`.
+    pub escape_offsets: bool,
+    /// The number of bytes per row when dumping bytes without offsets, like the bare hex values of `xxd -p`.
+    pub raw_hex_columns: Option<usize>,
+    /// Dump bytes like `hexdump -C`, with offsets, hex values and a character panel.
+    pub canonical: bool,
+    /// Write the number of each column above the rows of `offsets` or `canonical`.
+    pub ruler: bool,
+    /// Use the fonts instead of ASCII to decide which bytes are shown in the character panel.
+    pub panel_font_check: bool,
+    /// Skip every character that would be printed as is.
+    pub only_escaped: bool,
+    /// Append the name of the font that has the glyph to each printed non-ASCII character.
+    pub show_font: bool,
+    /// Record the distinct characters of the text, see [`Formatter::distinct_chars`].
+    pub collect_chars: bool,
+    /// Count the characters that each font is the first to have a glyph for, see [`Formatter::font_hits`].
+    pub count_font_hits: bool,
+    /// Treat every character as if the fonts had a glyph for it, so no fonts are needed.
+    pub no_font: bool,
+    pub encoding: Encoding,
+    /// Skip a byte order mark at the start of the text.
+    pub strip_bom: bool,
+    /// Append the Unicode name to each escaped character.
+    pub show_names: bool,
+    /// Print the Unicode name instead of the value of escaped characters that have one.
+    pub only_names: bool,
+    /// Append the Unicode general category, such as `Cc`, to each escaped character.
+    pub show_category: bool,
+    /// Append the Unicode block to each escaped character.
+    pub show_block: bool,
+    /// Always escape bidirectional control characters and record their positions.
+    pub warn_bidi: bool,
+    /// Always escape zero-width and other invisible characters, even if a font has a glyph for them.
+    pub visible_invisibles: bool,
+    /// Print soft hyphens as `-·`, as they are usually only shown where a word is broken.
+    pub show_soft_hyphen: bool,
+    /// Append the ASCII text that a printed character can be mistaken for, like `а[CONFUSABLE→a]`.
+    pub confusables: bool,
+    /// Write a JSON record for each character instead of the text.
+    pub json: bool,
+    /// Write each JSON record on its own line instead of in an array.
+    pub json_lines: bool,
+    /// Write a line for each character that tells how it is printed and why, instead of the text.
+    pub explain: bool,
+    /// Count the characters by their class, see [`Formatter::summary`].
+    pub summary: bool,
+    /// Count the bytes, characters, lines and escapes like `wc`, see [`Formatter::counts`].
+    pub count: bool,
+    /// Highlight escaped characters with ANSI colors by their [`EscapeReason`].
+    pub color: bool,
+    /// Print these characters as the given text, instead of deciding how to print them.
+    pub replacements: HashMap<char, String>,
+    /// Normalize the text before deciding how to print it, which loses the original bytes of the characters.
+    pub normalization: Option<Normalization>,
+    /// Escape grapheme clusters like emoji with modifiers as a whole if any of their glyphs is missing.
+    pub graphemes: bool,
+    /// Print the display width of each line instead of the text, where wide characters like CJK take two columns.
+    pub width_report: bool,
+    /// Print each invalid sequence as one replacement character `�`, or its escape if the fonts have no glyph for it.
+    pub replace_invalid: bool,
+    /// Print a run of at least this many invalid bytes as one `⟨N invalid bytes⟩`, instead of each of them.
+    pub collapse_invalid: Option<usize>,
+    /// Label or strip ANSI escape sequences like colors, instead of escaping their characters.
+    pub ansi: Option<AnsiMode>,
+    /// Mark these bytes wherever they are printed as values, in reverse video with [`FormatterOptions::color`] or
+    /// else between `⟦` and `⟧`.
+    pub highlighted_bytes: HashSet<u8>,
+    /// Mark these characters like [`FormatterOptions::highlighted_bytes`].
+    pub highlighted_chars: HashSet<char>,
+    /// Escape only the characters with a code point in one of these ranges, and print all others as is, whether the
+    /// fonts have a glyph for them or not.
+    pub escaped_ranges: Vec<RangeInclusive<u32>>,
+    /// Print every ASCII character as is, even control characters like `ESC`, and escape only the other characters.
+    pub ascii_passthrough: bool,
+    /// Treat these characters as if the fonts had a glyph for them.
+    pub always_rendered: HashSet<char>,
+    /// Treat these characters as if the fonts had no glyph for them, even ASCII characters.
+    pub never_rendered: HashSet<char>,
+    /// Log the font and glyph id that each looked up character is found with, see [`Formatter::with_glyph_log`].
+    pub log_glyphs: bool,
+    /// Break lines that would be wider than this number of columns, but never inside the text of a character.
+    pub wrap: Option<usize>,
+    /// The number of spaces at the start of the lines that continue a wrapped line.
+    pub wrap_indent: usize,
+}
+
+impl Default for FormatterOptions {
+    fn default() -> Self {
+        Self {
+            as_bytes: false,
+            all_as_hex: false,
+            base: NumericBase::Hex,
+            newline_escaped: false,
+            newline_as_hex: false,
+            carriage_return_as_hex: false,
+            crlf: false,
+            tab_as_hex: false,
+            formfeed_as_hex: false,
+            vtab_as_hex: false,
+            tab_arrow: false,
+            tab_width: 8,
+            space_as_circle: false,
+            space_glyph: '🞄',
+            space_as_hex: false,
+            show_whitespace: false,
+            byte_prefix: String::from("\\x"),
+            decimal_prefix: String::from("\\d"),
+            octal_prefix: String::from("\\"),
+            binary_prefix: String::from("\\b"),
+            group_nibbles: false,
+            uppercase: false,
+            byte_separator: String::new(),
+            byte_group: 1,
+            reverse_groups: false,
+            escape_style: EscapeStyle::Default,
+            html_named: false,
+            unicode_prefix: String::from("\\u"),
+            offsets: false,
+            columns: 16,
+            escape_offsets: false,
+            raw_hex_columns: None,
+            canonical: false,
+            ruler: false,
+            panel_font_check: false,
+            only_escaped: false,
+            show_font: false,
+            collect_chars: false,
+            count_font_hits: false,
+            no_font: false,
+            encoding: Encoding::Utf8,
+            strip_bom: false,
+            show_names: false,
+            only_names: false,
+            show_category: false,
+            show_block: false,
+            warn_bidi: false,
+            visible_invisibles: false,
+            show_soft_hyphen: false,
+            confusables: false,
+            json: false,
+            json_lines: false,
+            explain: false,
+            summary: false,
+            count: false,
+            color: false,
+            replacements: HashMap::new(),
+            normalization: None,
+            graphemes: false,
+            width_report: false,
+            replace_invalid: false,
+            collapse_invalid: None,
+            ansi: None,
+            highlighted_bytes: HashSet::new(),
+            highlighted_chars: HashSet::new(),
+            escaped_ranges: Vec::new(),
+            ascii_passthrough: false,
+            always_rendered: HashSet::new(),
+            never_rendered: HashSet::new(),
+            log_glyphs: false,
+            wrap: None,
+            wrap_indent: 0,
+        }
+    }
+}
+
+/// Writes text to `output`, escaping every character that is not renderable with `fonts`.
+pub struct Formatter<'a, W: Write> {
+    output: Output<W>,
+    fonts: &'a [FontCow<'a>],
+    font_names: Vec<String>,
+    options: FormatterOptions,
+    /// The offset of the current input in its file, which is added to the printed offsets.
+    start_offset: usize,
+    offset: usize,
+    row: Vec<u8>,
+    pending: Vec<u8>,
+    jobs: usize,
+    /// The encoding of the current input, which is only known after its start with [`Encoding::Auto`].
+    encoding: Encoding,
+    at_start: bool,
+    bom: Option<Bom>,
+    /// The byte position of the next character in the current input.
+    position: usize,
+    bidi_controls: Vec<(usize, char)>,
+    confusable_count: usize,
+    /// The characters of the ANSI escape sequence so far with `ansi`, with their original bytes.
+    ansi_sequence: Option<Vec<(Vec<u8>, char)>>,
+    /// The invalid sequences of the current run with `collapse_invalid`, which is written once it ends.
+    invalid_run: Vec<Vec<u8>>,
+    /// The display width of the current line so far with `width_report`, or `None` at the start of a line.
+    line_width: Option<usize>,
+    json_records: usize,
+    summary: CharSummary,
+    counts: Counts,
+    unrenderable_chars: BTreeSet<char>,
+    missing_chars: BTreeSet<char>,
+    distinct_chars: BTreeSet<char>,
+    /// The number of characters that each font was the first to have a glyph for.
+    font_hits: Vec<usize>,
+    invalid_bytes: BTreeSet<u8>,
+    /// The number of written bytes of the output after the last byte value, to know if a separator is needed.
+    byte_value_end: Option<usize>,
+    /// The number of byte values that were printed one after the other so far.
+    byte_run: usize,
+    /// Where the looked up glyphs are logged to with `log_glyphs`.
+    glyph_log: Option<Box<dyn Write + Send + 'a>>,
+}
+
+impl<'a, W: Write> Formatter<'a, W> {
+    pub fn new(output: W, fonts: &'a [FontCow<'a>], options: FormatterOptions) -> Self {
+        let encoding = options.encoding;
+        Self {
+            output: Output::new(output, options.tab_width.max(1)).with_wrap(options.wrap, options.wrap_indent),
+            fonts,
+            font_names: Vec::new(),
+            options,
+            start_offset: 0,
+            offset: 0,
+            row: Vec::new(),
+            pending: Vec::new(),
+            jobs: 1,
+            encoding,
+            at_start: true,
+            bom: None,
+            position: 0,
+            bidi_controls: Vec::new(),
+            confusable_count: 0,
+            line_width: None,
+            ansi_sequence: None,
+            invalid_run: Vec::new(),
+            json_records: 0,
+            summary: CharSummary::default(),
+            counts: Counts::default(),
+            unrenderable_chars: BTreeSet::new(),
+            missing_chars: BTreeSet::new(),
+            distinct_chars: BTreeSet::new(),
+            font_hits: vec![0; fonts.len()],
+            invalid_bytes: BTreeSet::new(),
+            byte_value_end: None,
+            byte_run: 0,
+            glyph_log: None,
+        }
+    }
+
+    /// Sets the names that are printed for the fonts with [`FormatterOptions::show_font`].
+    pub fn with_font_names(mut self, font_names: Vec<String>) -> Self {
+        self.font_names = font_names;
+        self
+    }
+
+    /// Sets where the lines of [`FormatterOptions::log_glyphs`] are written to, like standard error.
+    pub fn with_glyph_log(mut self, glyph_log: Box<dyn Write + Send + 'a>) -> Self {
+        self.glyph_log = Some(glyph_log);
+        self
+    }
+
+    /// Sets the number of threads that format the lines of large buffers in [`Formatter::process_str`].
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    pub fn options(&self) -> &FormatterOptions {
+        &self.options
+    }
+
+    /// Returns whether an invalid byte or a character that cannot be rendered was escaped.
+    pub fn found_unrenderable(&self) -> bool {
+        !self.unrenderable_chars.is_empty() || !self.invalid_bytes.is_empty()
+    }
+
+    /// The escaped characters that cannot be rendered, such as control characters or missing glyphs.
+    pub fn unrenderable_chars(&self) -> &BTreeSet<char> {
+        &self.unrenderable_chars
+    }
+
+    /// The characters that are not control characters and have no glyph in any of the fonts.
+    pub fn missing_chars(&self) -> &BTreeSet<char> {
+        &self.missing_chars
+    }
+
+    /// The characters that are not ASCII or control characters, if collected with
+    /// [`FormatterOptions::collect_chars`].
+    pub fn distinct_chars(&self) -> &BTreeSet<char> {
+        &self.distinct_chars
+    }
+
+    /// The number of characters that each font was the first to have a glyph for, in the order of the fonts, if
+    /// counted with [`FormatterOptions::count_font_hits`].
+    ///
+    /// ASCII characters are not looked up in the fonts, so they count for none of them.
+    pub fn font_hits(&self) -> &[usize] {
+        &self.font_hits
+    }
+
+    /// The byte order mark at the start of the last text.
+    pub fn bom(&self) -> Option<Bom> {
+        self.bom
+    }
+
+    /// The bidirectional control characters of the last text with their byte positions, see
+    /// [`FormatterOptions::warn_bidi`].
+    pub fn bidi_controls(&self) -> &[(usize, char)] {
+        &self.bidi_controls
+    }
+
+    /// The number of printed characters that are confusable with ASCII, see [`FormatterOptions::confusables`].
+    pub fn confusable_count(&self) -> usize {
+        self.confusable_count
+    }
+
+    /// The number of characters of each class in all the text so far, if [`FormatterOptions::summary`] is set.
+    pub fn summary(&self) -> &CharSummary {
+        &self.summary
+    }
+
+    /// The number of bytes, characters, lines and escapes in all the text so far, if [`FormatterOptions::count`] is
+    /// set.
+    pub fn counts(&self) -> &Counts {
+        &self.counts
+    }
+
+    /// The escaped bytes that are not part of valid UTF-8.
+    pub fn invalid_bytes(&self) -> &BTreeSet<u8> {
+        &self.invalid_bytes
+    }
+
+    /// Returns whether the last text written by the formatter ends with a new line.
+    pub fn ends_with_newline(&self) -> bool {
+        self.output.ends_with_newline
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.output.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output.inner
+    }
+
+    /// Writes text that is not part of the input, like a line number or a header, as it is.
+    ///
+    /// Unlike text written to [`Formatter::get_mut`], it counts for the columns of --wrap and tabs, and ends a run of
+    /// byte values.
+    pub fn write_prefix(&mut self, text: &str) -> io::Result<()> {
+        self.output.write_all(text.as_bytes())
+    }
+
+    pub fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.write_byte_separator()?;
+        self.write_separated_byte(byte)
+    }
+
+    /// Writes the separator before the next byte value, if it continues a run of bytes and starts a group.
+    fn write_byte_separator(&mut self) -> io::Result<()> {
+        // Anything else written since the last byte value ends the run of bytes
+        if self.byte_value_end != Some(self.output.written) {
+            self.byte_run = 0;
+        } else if self.byte_run.is_multiple_of(self.options.byte_group) {
+            write!(self.output, "{}", self.options.byte_separator)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a byte value after [`Formatter::write_byte_separator`], so it continues the run of bytes.
+    fn write_separated_byte(&mut self, byte: u8) -> io::Result<()> {
+        let highlighted = self.options.highlighted_bytes.contains(&byte);
+        self.write_highlight_start(highlighted)?;
+        self.write_byte_value(byte)?;
+        self.write_highlight_end(highlighted)?;
+        self.byte_value_end = Some(self.output.written);
+        self.byte_run += 1;
+        Ok(())
+    }
+
+    fn write_byte_value(&mut self, byte: u8) -> io::Result<()> {
+        // Hex escapes in C take any number of digits, so they could swallow the next character
+        match self.options.escape_style {
+            EscapeStyle::Rust if self.options.uppercase => return write!(self.output, "\\x{:02X}", byte),
+            EscapeStyle::Rust => return write!(self.output, "\\x{:02x}", byte),
+            EscapeStyle::C => return write!(self.output, "\\{:03o}", byte),
+            EscapeStyle::Default | EscapeStyle::Html => {}
+        }
+
+        match self.options.base {
+            NumericBase::Hex if self.options.uppercase => {
+                write!(self.output, "{}{:02X}", self.options.byte_prefix, byte)
+            }
+            NumericBase::Hex => write!(self.output, "{}{:02x}", self.options.byte_prefix, byte),
+            NumericBase::Decimal => write!(self.output, "{}{:03}", self.options.decimal_prefix, byte),
+            NumericBase::Octal => write!(self.output, "{}{:03o}", self.options.octal_prefix, byte),
+            NumericBase::Binary if self.options.group_nibbles => write!(
+                self.output,
+                "{}{:04b}_{:04b}",
+                self.options.binary_prefix,
+                byte >> 4,
+                byte & 0xf
+            ),
+            NumericBase::Binary => write!(self.output, "{}{:08b}", self.options.binary_prefix, byte),
+        }
+    }
+
+    fn write_highlight_start(&mut self, highlighted: bool) -> io::Result<()> {
+        match highlighted {
+            // The color codes take no columns, so they bypass the column tracking
+            true if self.options.color => self.output.write_uncounted("\x1b[7m"),
+            true => write!(self.output, "⟦"),
+            false => Ok(()),
+        }
+    }
+
+    fn write_highlight_end(&mut self, highlighted: bool) -> io::Result<()> {
+        match highlighted {
+            true if self.options.color => self.output.write_uncounted("\x1b[0m"),
+            true => write!(self.output, "⟧"),
+            false => Ok(()),
+        }
+    }
+
+    /// Sets the offset of the next input in its file, for inputs that do not start at the start of the file.
+    ///
+    /// The offset is kept until [`Formatter::finish`].
+    pub fn set_start_offset(&mut self, offset: usize) {
+        self.start_offset = offset;
+        self.position = offset;
+    }
+
+    /// Sets the encoding of the next input, like an encoding that was detected for it.
+    ///
+    /// The encoding is kept until [`Formatter::finish`].
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Writes every byte as a value, in rows with offsets if enabled.
+    pub fn dump_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.options.ruler && self.offset == 0 && self.row.is_empty() && !bytes.is_empty() {
+            self.write_ruler()?;
+        }
+
+        for byte in bytes {
+            if self.options.canonical {
+                self.row.push(*byte);
+                self.offset += 1;
+
+                if self.row.len() == self.options.columns {
+                    self.write_canonical_row()?;
+                }
+
+                continue;
+            }
+
+            // The bytes of a group are kept until it is complete, to write them in reverse
+            if self.options.reverse_groups {
+                self.row.push(*byte);
+
+                if self.row.len() == self.group_len() {
+                    self.write_reversed_group()?;
+                }
+
+                continue;
+            }
+
+            self.dump_byte(*byte, self.offset)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the byte at the offset, which differs from the offset of the written bytes in reversed groups.
+    fn dump_byte(&mut self, byte: u8, offset: usize) -> io::Result<()> {
+        if self.options.offsets {
+            let column = self.offset % self.options.columns;
+
+            if column == 0 {
+                write!(self.output, "{:08x} ", self.start_offset + self.offset)?;
+            }
+
+            if column.is_multiple_of(self.options.byte_group) {
+                write!(self.output, " ")?;
+            }
+            self.write_byte(byte)?;
+
+            if column + 1 == self.options.columns {
+                writeln!(self.output)?;
+            }
+        } else {
+            if self.options.escape_offsets {
+                // The offset is part of the value, so it is written after the separator
+                self.write_byte_separator()?;
+                write!(self.output, "@{}:", self.start_offset + offset)?;
+                self.write_separated_byte(byte)?;
+            } else {
+                self.write_byte(byte)?;
+            }
+
+            if self
+                .options
+                .raw_hex_columns
+                .is_some_and(|columns| (self.offset + 1).is_multiple_of(columns))
+            {
+                writeln!(self.output)?;
+            }
+        }
+
+        self.offset += 1;
+        Ok(())
+    }
+
+    /// Writes the number of each column above the rows, aligned with their byte values.
+    fn write_ruler(&mut self) -> io::Result<()> {
+        use std::fmt::Write as _;
+
+        let mut ruler = String::new();
+
+        // The numbers are right aligned, so their digits are below the digits of the values
+        if self.options.canonical {
+            ruler.push_str("          ");
+
+            for column in 0..self.options.columns {
+                let _ = write!(ruler, "{:02x} ", column % 0x100);
+
+                if column % 8 == 7 {
+                    ruler.push(' ');
+                }
+            }
+        } else {
+            ruler.push_str("         ");
+            let width = self.byte_value_width();
+
+            for column in 0..self.options.columns {
+                if column.is_multiple_of(self.options.byte_group) {
+                    ruler.push(' ');
+                }
+
+                let _ = write!(ruler, "{:>1$}", format!("{:02x}", column % 0x100), width);
+            }
+        }
+
+        writeln!(self.output, "{}", ruler.trim_end())
+    }
+
+    /// Returns the display width of the byte values, which is the same for every byte.
+    fn byte_value_width(&self) -> usize {
+        let options = &self.options;
+
+        match (options.escape_style, options.base) {
+            (EscapeStyle::Rust | EscapeStyle::C, _) => 4,
+            (_, NumericBase::Hex) => options.byte_prefix.width() + 2,
+            (_, NumericBase::Decimal) => options.decimal_prefix.width() + 3,
+            (_, NumericBase::Octal) => options.octal_prefix.width() + 3,
+            (_, NumericBase::Binary) if options.group_nibbles => options.binary_prefix.width() + 9,
+            (_, NumericBase::Binary) => options.binary_prefix.width() + 8,
+        }
+    }
+
+    /// Returns the length of the group that starts at the current offset, which is shorter at the end of a row.
+    fn group_len(&self) -> usize {
+        match self.options.offsets {
+            true => self
+                .options
+                .byte_group
+                .min(self.options.columns - self.offset % self.options.columns),
+            false => self.options.byte_group,
+        }
+    }
+
+    fn write_reversed_group(&mut self) -> io::Result<()> {
+        let group = std::mem::take(&mut self.row);
+        let start = self.offset;
+
+        for (index, byte) in group.iter().enumerate().rev() {
+            self.dump_byte(*byte, start + index)?;
+        }
+
+        self.row = group;
+        self.row.clear();
+        Ok(())
+    }
+
+    fn write_canonical_row(&mut self) -> io::Result<()> {
+        let row = std::mem::take(&mut self.row);
+        write!(self.output, "{:08x}  ", self.start_offset + self.offset - row.len())?;
+
+        for column in 0..self.options.columns {
+            match row.get(column) {
+                Some(byte) => {
+                    let highlighted = self.options.highlighted_bytes.contains(byte);
+                    self.write_highlight_start(highlighted)?;
+                    write!(self.output, "{:02x}", byte)?;
+                    self.write_highlight_end(highlighted)?;
+                    write!(self.output, " ")?;
+                }
+                None => write!(self.output, "   ")?,
+            }
+
+            if column % 8 == 7 {
+                write!(self.output, " ")?;
+            }
+        }
+
+        if !self.options.columns.is_multiple_of(8) {
+            write!(self.output, " ")?;
+        }
+
+        write!(self.output, "|")?;
+
+        for byte in &row {
+            write!(self.output, "{}", self.panel_char(*byte))?;
+        }
+
+        writeln!(self.output, "|")?;
+        self.row = row;
+        self.row.clear();
+        Ok(())
+    }
+
+    fn log_glyph(&mut self, char: char, font_index: Option<usize>) {
+        let Some(glyph_log) = &mut self.glyph_log else {
+            return;
+        };
+
+        // The log is only for debugging, so failing to write it is not an error
+        let Some(index) = font_index else {
+            let _ = writeln!(
+                glyph_log,
+                "Debug: U+{:04X} {:?} is in none of the fonts",
+                u32::from(char),
+                char
+            );
+            return;
+        };
+
+        let font = match self.font_names.get(index) {
+            Some(name) => format!("'{}'", name),
+            None => format!("#{}", index + 1),
+        };
+
+        // The cached coverage does not know the glyph ids
+        let _ = match &self.fonts[index] {
+            FontCow::Coverage(_) => writeln!(
+                glyph_log,
+                "Debug: U+{:04X} {:?} is in the cached coverage of {}",
+                u32::from(char),
+                char,
+                font
+            ),
+            font_cow => writeln!(
+                glyph_log,
+                "Debug: U+{:04X} {:?} has glyph {} in {}",
+                u32::from(char),
+                char,
+                font_cow.glyph_id(char).0,
+                font
+            ),
+        };
+    }
+
+    /// Returns whether the fonts have no glyph for the character, given the font that [`find_font_for_char`] found.
+    fn is_missing(&self, char: char, font_index: Option<usize>) -> bool {
+        let options = &self.options;
+        options.never_rendered.contains(&char)
+            || (font_index.is_none()
+                && !char.is_ascii()
+                && !options.no_font
+                && !options.always_rendered.contains(&char))
+    }
+
+    fn has_glyph(&self, char: char) -> bool {
+        let options = &self.options;
+        !options.never_rendered.contains(&char)
+            && (options.no_font || options.always_rendered.contains(&char) || is_char_in_fonts(self.fonts, char))
+    }
+
+    fn panel_char(&self, byte: u8) -> char {
+        let char = char::from(byte);
+
+        if byte.is_ascii_graphic()
+            || byte == b' '
+            || (self.options.panel_font_check
+                && !char.is_ascii()
+                && !char.is_control()
+                && !char.is_whitespace()
+                && self.has_glyph(char))
+        {
+            char
+        } else {
+            '.'
+        }
+    }
+
+    /// Completes the current input, so the next buffer is treated as the start of a new one.
+    ///
+    /// This has to be called after the last buffer, as a character split across buffers is only written once it
+    /// is complete.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            let pending = self.start_input(&pending);
+            self.process_complete(pending)?;
+        } else if self.at_start {
+            self.bom = None;
+            self.bidi_controls.clear();
+        }
+
+        self.process_ansi_sequence()?;
+        self.process_invalid_run()?;
+        self.encoding = self.options.encoding;
+        self.at_start = true;
+
+        if self.options.reverse_groups && !self.row.is_empty() {
+            self.write_reversed_group()?;
+        }
+
+        if self.options.canonical {
+            if !self.row.is_empty() {
+                self.write_canonical_row()?;
+            }
+
+            if self.offset > 0 {
+                writeln!(self.output, "{:08x}", self.start_offset + self.offset)?;
+            }
+        } else {
+            // The last row of the bytes ends the line, unless it is complete
+            let columns = match self.options.offsets {
+                true => Some(self.options.columns),
+                false => self.options.raw_hex_columns,
+            };
+
+            if columns.is_some_and(|columns| !self.offset.is_multiple_of(columns)) {
+                writeln!(self.output)?;
+            }
+        }
+
+        if let Some(line_width) = self.line_width.take() {
+            writeln!(self.output, "{}", line_width)?;
+        }
+
+        if self.options.json && !self.options.json_lines {
+            let end = if self.json_records == 0 { "[]" } else { "\n]" };
+            writeln!(self.output, "{}", end)?;
+        }
+
+        self.start_offset = 0;
+        self.offset = 0;
+        self.position = 0;
+        self.json_records = 0;
+        self.byte_run = 0;
+        Ok(())
+    }
+
+    /// Ends a record of the input with its delimiter, which is written as is instead of formatted, like the NUL at the
+    /// end of each path of `find -print0`.
+    ///
+    /// The delimiter completes the characters before it, but unlike [`Formatter::finish`], the next buffer continues
+    /// the current input.
+    pub fn end_record(&mut self, delimiter: u8) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            let pending = self.start_input(&pending);
+            self.process_complete(pending)?;
+        }
+
+        self.process_ansi_sequence()?;
+        self.process_invalid_run()?;
+
+        if self.options.count {
+            self.counts.bytes += 1;
+            self.counts.chars += 1;
+        }
+        self.position += 1;
+        self.output.write_all(&[delimiter])
+    }
+
+    pub fn write_char(&mut self, char: char) -> io::Result<()> {
+        let mut bytes = [0; 4];
+        self.write_escaped(char.encode_utf8(&mut bytes).as_bytes(), char)
+    }
+
+    /// Writes the text, which may end in the middle of a character that is continued by the next buffer.
+    pub fn process_str(&mut self, buffer: &[u8]) -> io::Result<()> {
+        if self.options.count {
+            self.counts.bytes += buffer.len();
+        }
+
+        if self.pending.is_empty() && !(self.at_start && Bom::is_incomplete(buffer)) {
+            let buffer = self.start_input(buffer);
+            let complete = self.complete_len(buffer);
+            self.process_complete(&buffer[..complete])?;
+            self.pending.extend_from_slice(&buffer[complete..]);
+        } else {
+            let mut pending = std::mem::take(&mut self.pending);
+            pending.extend_from_slice(buffer);
+
+            if self.at_start {
+                if Bom::is_incomplete(&pending) {
+                    self.pending = pending;
+                    return Ok(());
+                }
+
+                let start = pending.len() - self.start_input(&pending).len();
+                pending.drain(..start);
+            }
+
+            let complete = self.complete_len(&pending);
+            self.process_complete(&pending[..complete])?;
+            pending.drain(..complete);
+            self.pending = pending;
+        }
+
+        Ok(())
+    }
+
+    /// Detects the byte order mark if the buffer is the start of the input, and skips it with
+    /// [`FormatterOptions::strip_bom`].
+    fn start_input<'b>(&mut self, buffer: &'b [u8]) -> &'b [u8] {
+        if !self.at_start {
+            return buffer;
+        }
+
+        self.at_start = false;
+        self.bom = Bom::detect(buffer);
+        self.bidi_controls.clear();
+
+        if self.encoding == Encoding::Auto {
+            self.encoding = Encoding::from_bom(self.bom);
+        }
+
+        match self.bom.filter(|_| self.options.strip_bom) {
+            Some(bom) => {
+                self.position = self.start_offset + bom.bytes().len();
+                &buffer[bom.bytes().len()..]
+            }
+            None => buffer,
+        }
+    }
+
+    fn process_complete(&mut self, buffer: &[u8]) -> io::Result<()> {
+        // The characters are counted before the buffer is split, so each of them is counted once
+        if self.options.count {
+            let counts = &mut self.counts;
+            self.encoding.for_each_char(buffer, |_, char| {
+                counts.chars += usize::from(char.is_some());
+                counts.lines += usize::from(char == Some('\n'));
+            });
+        }
+
+        // JSON records and byte values are separated depending on the ones before, which other threads do not know, and
+        // the log of the glyphs would be out of order with several workers
+        let jobs = if self.options.json
+            || !self.options.byte_separator.is_empty()
+            || self.options.log_glyphs
+            || self.options.collapse_invalid.is_some()
+        {
+            1
+        } else {
+            self.jobs
+        };
+        let parts = split_lines(buffer, self.encoding, jobs);
+
+        // An unfinished escape sequence continues in the first part, which the other threads do not know
+        if parts.len() <= 1 || self.ansi_sequence.is_some() {
+            return self.process_sequential(buffer);
+        }
+
+        // Each part is formatted into its own buffer, which are then written in order
+        let mut position = self.position;
+        let results: Vec<_> = std::thread::scope(|scope| {
+            let workers: Vec<_> = parts
+                .into_iter()
+                .map(|part| {
+                    let mut formatter = Formatter::new(Vec::new(), self.fonts, self.options.clone())
+                        .with_font_names(self.font_names.clone());
+                    formatter.encoding = self.encoding;
+                    formatter.position = position;
+
+                    // Only the first part may not start at the beginning of a line
+                    if position == self.position {
+                        formatter.output.column = self.output.column;
+                        formatter.line_width = self.line_width;
+                    }
+                    position += part.len();
+                    scope.spawn(move || formatter.process_sequential(part).map(|()| formatter))
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect::<io::Result<_>>()
+        })?;
+
+        for result in results {
+            self.output.write_all(&result.output.inner)?;
+            // The written colors do not take columns
+            self.output.column = result.output.column;
+            self.unrenderable_chars.extend(result.unrenderable_chars);
+            self.missing_chars.extend(result.missing_chars);
+            self.distinct_chars.extend(result.distinct_chars);
+            for (hits, result_hits) in self.font_hits.iter_mut().zip(result.font_hits) {
+                *hits += result_hits;
+            }
+            self.invalid_bytes.extend(result.invalid_bytes);
+            self.bidi_controls.extend(result.bidi_controls);
+            self.confusable_count += result.confusable_count;
+            self.summary.add(&result.summary);
+            self.counts.escaped += result.counts.escaped;
+            self.line_width = result.line_width;
+            self.ansi_sequence = result.ansi_sequence;
+        }
+
+        self.position = position;
+        Ok(())
+    }
+
+    /// Returns the length of the start of the buffer that can be processed without the next buffer.
+    fn complete_len(&self, buffer: &[u8]) -> usize {
+        let complete = buffer.len() - self.encoding.incomplete_suffix(buffer);
+
+        // Characters after the last one that cannot be combined with the following ones wait for the next buffer
+        let complete = match self.options.normalization {
+            Some(_) => self
+                .encoding
+                .last_ascii_end(&buffer[..complete], is_normalization_boundary)
+                .unwrap_or(0),
+            None => complete,
+        };
+
+        // Grapheme clusters always end at control characters, except for a carriage return before a new line
+        let complete = if self.options.graphemes {
+            self.encoding
+                .last_ascii_end(&buffer[..complete], |byte| byte.is_ascii_control() && byte != b'\r')
+                .unwrap_or(0)
+        } else {
+            complete
+        };
+
+        // A carriage return waits for the next buffer, which may start with its new line
+        if self.merges_crlf() {
+            complete - self.encoding.trailing_carriage_return(&buffer[..complete])
+        } else {
+            complete
+        }
+    }
+
+    fn merges_crlf(&self) -> bool {
+        let options = &self.options;
+        options.crlf
+            && !options.width_report
+            && !options.carriage_return_as_hex
+            && !options.newline_as_hex
+            && !options.all_as_hex
+            && !options.json
+    }
+
+    fn process_sequential(&mut self, buffer: &[u8]) -> io::Result<()> {
+        let merges_crlf = self.merges_crlf();
+
+        if self.options.normalization.is_none() && !self.options.graphemes {
+            let mut carriage_return = None;
+            let mut result = Ok(());
+            // The characters after an error are skipped, as the closure cannot return early
+            self.encoding.for_each_char(buffer, |original_bytes, char| {
+                if result.is_ok() {
+                    result = self.process_next(&mut carriage_return, merges_crlf, original_bytes, char);
+                }
+            });
+            result?;
+            return self.process_held(carriage_return);
+        }
+
+        let normalized = self
+            .options
+            .normalization
+            .map(|normalization| normalize(self.encoding, buffer, normalization));
+        let mut chars = Vec::new();
+
+        match &normalized {
+            Some((normalized, pieces)) => {
+                for piece in pieces {
+                    match piece {
+                        Piece::Text(range) => {
+                            let text = &normalized[range.clone()];
+                            chars.extend(
+                                text.char_indices().map(|(start, char)| {
+                                    (&text.as_bytes()[start..start + char.len_utf8()], Some(char))
+                                }),
+                            );
+                        }
+                        Piece::Invalid(bytes) => chars.push((bytes, None)),
+                    }
+                }
+            }
+            None => self
+                .encoding
+                .for_each_char(buffer, |original_bytes, char| chars.push((original_bytes, char))),
+        }
+
+        let mut carriage_return = None;
+
+        if self.options.graphemes {
+            self.process_graphemes(&chars, &mut carriage_return, merges_crlf)?;
+        } else {
+            for (original_bytes, char) in chars {
+                self.process_next(&mut carriage_return, merges_crlf, original_bytes, char)?;
+            }
+        }
+
+        self.process_held(carriage_return)
+    }
+
+    /// Processes the characters by grapheme clusters, where a cluster is escaped as a whole if a glyph of it is missing.
+    fn process_graphemes<'b>(
+        &mut self,
+        chars: &[(&'b [u8], Option<char>)],
+        carriage_return: &mut Option<&'b [u8]>,
+        merges_crlf: bool,
+    ) -> io::Result<()> {
+        let mut index = 0;
+
+        while index < chars.len() {
+            let (original_bytes, char) = chars[index];
+            if char.is_none() {
+                self.process_next(carriage_return, merges_crlf, original_bytes, char)?;
+                index += 1;
+                continue;
+            }
+
+            // The run of valid characters, as invalid bytes always break clusters
+            let run_len = chars[index..].iter().take_while(|(_, char)| char.is_some()).count();
+            let text: String = chars[index..index + run_len]
+                .iter()
+                .filter_map(|(_, char)| *char)
+                .collect();
+
+            for cluster in text.graphemes(true) {
+                let cluster_len = cluster.chars().count();
+                // A zero width joiner inside a cluster joins its parts, like the members of an emoji family
+                let is_joined = cluster.trim_end_matches('\u{200d}').contains('\u{200d}');
+                let is_missing = cluster_len > 1
+                    && cluster
+                        .chars()
+                        .filter(|char| !(is_joined && chars::is_emoji_joiner(*char)))
+                        .any(|char| !char.is_ascii() && !char.is_control() && !self.has_glyph(char));
+
+                let cluster_rendering = match (is_missing, is_joined) {
+                    (true, _) => ClusterRendering::Escaped,
+                    (false, true) => ClusterRendering::Joined,
+                    (false, false) => ClusterRendering::ByCharacter,
+                };
+
+                for (original_bytes, char) in &chars[index..index + cluster_len] {
+                    if cluster_rendering == ClusterRendering::ByCharacter {
+                        self.process_next(carriage_return, merges_crlf, original_bytes, *char)?;
+                    } else {
+                        self.process_invalid_run()?;
+                        self.process_held(carriage_return.take())?;
+                        self.process_char_with(original_bytes, *char, cluster_rendering)?;
+                        self.position += original_bytes.len();
+                    }
+                }
+
+                index += cluster_len;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Processes the carriage return that was held back at the end of the buffer.
+    fn process_held(&mut self, carriage_return: Option<&[u8]>) -> io::Result<()> {
+        if let Some(carriage_return) = carriage_return {
+            self.process_char(carriage_return, Some('\r'))?;
+            self.position += carriage_return.len();
+        }
+
+        Ok(())
+    }
+
+    /// Processes the next character, where a carriage return is held back if it may be followed by a new line.
+    fn process_next<'b>(
+        &mut self,
+        carriage_return: &mut Option<&'b [u8]>,
+        merges_crlf: bool,
+        original_bytes: &'b [u8],
+        char: Option<char>,
+    ) -> io::Result<()> {
+        if self.ansi_sequence.is_some() && self.continue_ansi_sequence(original_bytes, char)? {
+            return Ok(());
+        }
+
+        if let Some(carriage_return) = carriage_return.take() {
+            if char == Some('\n') {
+                self.process_crlf()?;
+                self.position += carriage_return.len() + original_bytes.len();
+                return Ok(());
+            }
+
+            self.process_char(carriage_return, Some('\r'))?;
+            self.position += carriage_return.len();
+        }
+
+        // The invalid bytes are held until the run ends, as it may continue in the next buffer
+        if self.options.collapse_invalid.is_some() {
+            if char.is_none() {
+                self.invalid_run.push(original_bytes.to_vec());
+                return Ok(());
+            }
+
+            self.process_invalid_run()?;
+        }
+
+        if merges_crlf && char == Some('\r') {
+            *carriage_return = Some(original_bytes);
+            return Ok(());
+        }
+
+        if self.options.ansi.is_some() && char == Some('\x1b') {
+            self.ansi_sequence = Some(vec![(original_bytes.to_vec(), '\x1b')]);
+            return Ok(());
+        }
+
+        self.process_char(original_bytes, char)?;
+        self.position += original_bytes.len();
+        Ok(())
+    }
+
+    /// Adds the character to the ANSI escape sequence, and writes the sequence once it is complete.
+    ///
+    /// Returns false if the character does not belong to the sequence, which is then processed as usual.
+    fn continue_ansi_sequence(&mut self, original_bytes: &[u8], char: Option<char>) -> io::Result<bool> {
+        let sequence = self.ansi_sequence.as_mut().unwrap();
+
+        match char {
+            // A control sequence introducer, `ESC [`
+            Some(char @ '[') if sequence.len() == 1 => sequence.push((original_bytes.to_vec(), char)),
+            // Parameters and intermediate bytes, up to a sane length
+            Some(char @ '\x20'..='\x3f') if sequence.len() > 1 && sequence.len() < MAX_ANSI_SEQUENCE_LEN => {
+                sequence.push((original_bytes.to_vec(), char))
+            }
+            // The final byte
+            Some(char @ '\x40'..='\x7e') if sequence.len() > 1 => {
+                let sequence = self.ansi_sequence.take().unwrap();
+                let parameters: String = sequence[2..].iter().map(|(_, char)| *char).collect();
+
+                match (self.options.ansi, char) {
+                    (Some(AnsiMode::Strip), _) => {}
+                    (_, 'm') => write!(self.output, "⟨SGR:{}⟩", parameters)?,
+                    _ => write!(self.output, "⟨CSI:{}{}⟩", parameters, char)?,
+                }
+
+                self.position += sequence.iter().map(|(bytes, _)| bytes.len()).sum::<usize>() + original_bytes.len();
+            }
+            _ => {
+                self.process_ansi_sequence()?;
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Processes the characters of an incomplete ANSI escape sequence as usual.
+    fn process_ansi_sequence(&mut self) -> io::Result<()> {
+        for (original_bytes, char) in self.ansi_sequence.take().unwrap_or_default() {
+            self.process_char(&original_bytes, Some(char))?;
+            self.position += original_bytes.len();
+        }
+
+        Ok(())
+    }
+
+    /// Writes a carriage return followed by a new line as one token.
+    fn process_crlf(&mut self) -> io::Result<()> {
+        self.output.start_unit();
+        self.write_crlf()?;
+        self.output.end_unit()
+    }
+
+    fn write_crlf(&mut self) -> io::Result<()> {
+        if self.options.summary {
+            self.summary.whitespace += 2;
+        }
+
+        if self.options.count {
+            self.counts.escaped += 1;
+        }
+
+        let color = EscapeReason::Newline.color().filter(|_| self.options.color);
+        if let Some(color) = color {
+            self.output.write_uncounted(&format!("\x1b[{}m", color))?;
+        }
+
+        if self.options.escape_offsets {
+            write!(self.output, "@{}:", self.position)?;
+        }
+
+        if self.options.newline_escaped {
+            write!(self.output, "\\r\\n")?;
+        } else {
+            write!(self.output, "⏎")?;
+        }
+
+        if color.is_some() {
+            self.output.write_uncounted("\x1b[0m")?;
+        }
+
+        if !self.options.newline_escaped {
+            writeln!(self.output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the run of invalid bytes as one token if it is long enough, or else each of its sequences as usual.
+    fn process_invalid_run(&mut self) -> io::Result<()> {
+        let run = std::mem::take(&mut self.invalid_run);
+        let len: usize = run.iter().map(Vec::len).sum();
+
+        if self.options.collapse_invalid.is_none_or(|min_len| len < min_len) {
+            for original_bytes in run {
+                self.process_char(&original_bytes, None)?;
+                self.position += original_bytes.len();
+            }
+
+            return Ok(());
+        }
+
+        if self.options.summary {
+            self.summary.invalid_bytes += len;
+        }
+        if self.options.count {
+            self.counts.escaped += 1;
+        }
+        self.invalid_bytes.extend(run.concat());
+
+        let color = EscapeReason::InvalidBytes.color().filter(|_| self.options.color);
+        self.output.start_unit();
+
+        if let Some(color) = color {
+            self.output.write_uncounted(&format!("\x1b[{}m", color))?;
+        }
+
+        if self.options.escape_offsets {
+            write!(self.output, "@{}:", self.position)?;
+        }
+
+        write!(self.output, "⟨{} invalid bytes⟩", len)?;
+
+        if color.is_some() {
+            self.output.write_uncounted("\x1b[0m")?;
+        }
+
+        self.output.end_unit()?;
+        self.position += len;
+        Ok(())
+    }
+
+    /// Writes a character, or its original bytes if they are invalid in the encoding.
+    fn process_char(&mut self, original_bytes: &[u8], char: Option<char>) -> io::Result<()> {
+        self.process_char_with(original_bytes, char, ClusterRendering::ByCharacter)
+    }
+
+    /// Like [`Formatter::process_char`], but follows how the grapheme cluster of the character is printed.
+    fn process_char_with(
+        &mut self,
+        original_bytes: &[u8],
+        char: Option<char>,
+        cluster_rendering: ClusterRendering,
+    ) -> io::Result<()> {
+        // The text of a character is wrapped as a whole, so an escape sequence like `\u{1F600}` is never split
+        self.output.start_unit();
+        self.print_char(original_bytes, char, cluster_rendering)?;
+        self.output.end_unit()
+    }
+
+    fn print_char(
+        &mut self,
+        original_bytes: &[u8],
+        char: Option<char>,
+        cluster_rendering: ClusterRendering,
+    ) -> io::Result<()> {
+        if self.options.width_report {
+            match char {
+                Some('\n') => writeln!(self.output, "{}", self.line_width.take().unwrap_or(0))?,
+                Some(char) => {
+                    let line_width = self.line_width.get_or_insert(0);
+                    *line_width = chars::next_column(*line_width, char, self.options.tab_width.max(1));
+                }
+                // Invalid bytes are usually shown as one replacement character
+                None => *self.line_width.get_or_insert(0) += 1,
+            }
+
+            return Ok(());
+        }
+
+        let replacement = char
+            .and_then(|char| self.options.replacements.get(&char))
+            .filter(|_| !self.options.json);
+
+        if let Some(replacement) = replacement {
+            write!(self.output, "{}", replacement)?;
+            return Ok(());
+        }
+
+        let is_joiner = cluster_rendering == ClusterRendering::Joined && char.is_some_and(chars::is_emoji_joiner);
+
+        let (rendering, font_index) = match char {
+            // The joiners need no glyph of their own, as the font renders the joined sequence
+            Some(_) if is_joiner => (Rendering::Literal, None),
+            Some(char) => match self.classify(char) {
+                (Rendering::Literal, font_index) if cluster_rendering == ClusterRendering::Escaped => {
+                    self.unrenderable_chars.insert(char);
+                    (Rendering::Escaped(EscapeReason::MissingGlyph), font_index)
+                }
+                classified => classified,
+            },
+            None => {
+                self.invalid_bytes.extend(original_bytes);
+
+                if self.options.replace_invalid && self.has_glyph(char::REPLACEMENT_CHARACTER) {
+                    (Rendering::Short("\u{fffd}", EscapeReason::InvalidBytes), None)
+                } else {
+                    (Rendering::Escaped(EscapeReason::InvalidBytes), None)
+                }
+            }
+        };
+
+        if self.options.summary {
+            match char {
+                None => self.summary.invalid_bytes += original_bytes.len(),
+                Some(c) if c.is_whitespace() => self.summary.whitespace += 1,
+                Some(c) if c.is_control() => self.summary.control += 1,
+                Some(c) if self.is_missing(c, font_index) && !is_joiner => self.summary.missing_glyph += 1,
+                Some(_) => self.summary.printable += 1,
+            }
+        }
+
+        if self.options.count && !matches!(rendering, Rendering::Literal) {
+            self.counts.escaped += 1;
+        }
+
+        if self.options.json {
+            return self.write_record(original_bytes, char, rendering);
+        }
+
+        let explanation = match self.options.explain {
+            true if matches!(rendering, Rendering::Literal) && self.options.only_escaped => return Ok(()),
+            true => {
+                self.write_explanation_start(original_bytes, char)?;
+                Some(self.explanation(char, rendering, font_index, cluster_rendering, is_joiner))
+            }
+            false => None,
+        };
+
+        let color = match rendering {
+            Rendering::Literal => None,
+            Rendering::Short(_, reason) | Rendering::Glyph(_, reason) | Rendering::Escaped(reason) => reason.color(),
+        }
+        .filter(|_| self.options.color);
+
+        let highlighted = char.is_some_and(|char| self.options.highlighted_chars.contains(&char))
+            && !(matches!(rendering, Rendering::Literal) && self.options.only_escaped);
+        self.write_highlight_start(highlighted)?;
+
+        // The color codes take no columns, so they bypass the column tracking
+        if let Some(color) = color {
+            self.output.write_uncounted(&format!("\x1b[{}m", color))?;
+        }
+
+        // The offset of a character with several bytes is the offset of its first byte
+        if self.options.escape_offsets && !matches!(rendering, Rendering::Literal) {
+            write!(self.output, "@{}:", self.position)?;
+        }
+
+        // Invalid bytes are then printed like one replacement character
+        let (original_bytes, char) = match char {
+            None if self.options.replace_invalid => ("\u{fffd}".as_bytes(), Some(char::REPLACEMENT_CHARACTER)),
+            _ => (original_bytes, char),
+        };
+
+        match (rendering, char) {
+            (Rendering::Literal, _) if self.options.explain => write!(self.output, "literal")?,
+            (Rendering::Literal, _) if self.options.only_escaped => {}
+            (Rendering::Literal, Some(char)) => {
+                match (self.options.escape_style, char) {
+                    (EscapeStyle::Html, '&' | '<' | '>' | '"') => {
+                        write!(self.output, "&{};", chars::html_entity(char).unwrap())?
+                    }
+                    (EscapeStyle::Rust | EscapeStyle::C, '"' | '\\') => write!(self.output, "\\{}", char)?,
+                    _ => write!(self.output, "{}", char)?,
+                }
+
+                if let Some(index) = font_index.filter(|_| self.options.show_font) {
+                    match self.font_names.get(index) {
+                        Some(name) => write!(self.output, "[{}]", name)?,
+                        None => write!(self.output, "[#{}]", index + 1)?,
+                    }
+                }
+
+                if let Some(confusable) = chars::ascii_confusable(char).filter(|_| self.options.confusables) {
+                    write!(self.output, "[CONFUSABLE→{}]", confusable)?;
+                    self.confusable_count += 1;
+                }
+            }
+            // HTML has no short escape sequences like `\n`
+            (Rendering::Short(_, reason), Some(char))
+                if self.options.escape_style == EscapeStyle::Html && reason != EscapeReason::Space =>
+            {
+                self.write_escaped(original_bytes, char)?;
+            }
+            (Rendering::Short(_, EscapeReason::Tab), _) if self.options.tab_arrow => {
+                let width = self.output.tab_width - self.output.column % self.output.tab_width;
+                write!(self.output, "→{:1$}", "", width.saturating_sub(1))?;
+            }
+            (Rendering::Short(text, _), _) => write!(self.output, "{}", text)?,
+            (Rendering::Glyph(glyph, _), _) => write!(self.output, "{}", glyph)?,
+            (_, Some(char)) => self.write_escaped(original_bytes, char)?,
+            (_, None) => {
+                for byte in original_bytes {
+                    self.write_byte(*byte)?;
+                }
+            }
+        }
+
+        if color.is_some() {
+            self.output.write_uncounted("\x1b[0m")?;
+        }
+
+        self.write_highlight_end(highlighted)?;
+
+        if let Some(explanation) = explanation {
+            writeln!(self.output, " ({})", explanation)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the offset, code point and name of the character, or its bytes if they are invalid, before the arrow to
+    /// how it is printed.
+    fn write_explanation_start(&mut self, original_bytes: &[u8], char: Option<char>) -> io::Result<()> {
+        write!(self.output, "{}: ", self.position)?;
+
+        let Some(char) = char else {
+            for byte in original_bytes {
+                write!(self.output, "0x{:02X} ", byte)?;
+            }
+            return write!(self.output, "→ ");
+        };
+
+        write!(self.output, "U+{:04X}", u32::from(char))?;
+        if let Some(name) = unicode_names2::name(char) {
+            write!(self.output, " {}", name)?;
+        }
+        write!(self.output, " → ")
+    }
+
+    /// Returns why the character is printed this way, for [`FormatterOptions::explain`].
+    fn explanation(
+        &self,
+        char: Option<char>,
+        rendering: Rendering,
+        font_index: Option<usize>,
+        cluster_rendering: ClusterRendering,
+        is_joiner: bool,
+    ) -> String {
+        let options = &self.options;
+        let reason = match rendering {
+            Rendering::Short(_, reason) | Rendering::Glyph(_, reason) | Rendering::Escaped(reason) => reason,
+            Rendering::Literal => {
+                let Some(char) = char else {
+                    return String::from("invalid bytes");
+                };
+
+                return match font_index {
+                    _ if is_joiner => String::from("joins the emoji sequence"),
+                    _ if !options.escaped_ranges.is_empty() => String::from("outside of --range"),
+                    _ if options.ascii_passthrough && char.is_ascii() => String::from("ASCII with --ascii-passthrough"),
+                    _ if options.always_rendered.contains(&char) => String::from("--always-render"),
+                    _ if options.no_font => String::from("--no-font"),
+                    Some(index) => match self.font_names.get(index) {
+                        Some(name) => format!("found in {}", name),
+                        None => format!("found in font #{}", index + 1),
+                    },
+                    None if char == '\n' => String::from("new line"),
+                    None if char.is_ascii() => String::from("ASCII"),
+                    None => String::from("printable"),
+                };
+            }
+        };
+
+        let explanation = match reason {
+            EscapeReason::MissingGlyph if cluster_rendering == ClusterRendering::Escaped => {
+                "a glyph of its grapheme cluster is missing"
+            }
+            EscapeReason::MissingGlyph if char.is_some_and(|char| options.never_rendered.contains(&char)) => {
+                "--never-render"
+            }
+            EscapeReason::InvalidBytes => "not valid in the encoding",
+            EscapeReason::All => "everything is escaped with --all",
+            EscapeReason::Newline => "new line",
+            EscapeReason::CarriageReturn => "carriage return",
+            EscapeReason::Tab => "tab",
+            EscapeReason::Space => "space",
+            EscapeReason::Control => "control character",
+            EscapeReason::Whitespace => "whitespace",
+            EscapeReason::MissingGlyph => "in none of the fonts",
+            EscapeReason::BidiControl => "bidirectional control character",
+            EscapeReason::Invisible => "invisible character",
+            EscapeReason::Range => "inside of --range",
+        };
+
+        String::from(explanation)
+    }
+
+    /// Decides how the character is printed, and returns the index of the font that has it if it is not ASCII.
+    fn classify(&mut self, char: char) -> (Rendering, Option<usize>) {
+        let options = &self.options;
+
+        // The glyphs do not matter if everything is escaped, unless they are counted, or if the character is overridden
+        let is_overridden = options.always_rendered.contains(&char) || options.never_rendered.contains(&char);
+        let font_index = if char.is_ascii()
+            || options.no_font
+            || is_overridden
+            || (options.all_as_hex && !options.summary && !options.count_font_hits)
+        {
+            None
+        } else {
+            let font_index = find_font_for_char(self.fonts, char);
+            if self.options.log_glyphs {
+                self.log_glyph(char, font_index);
+            }
+            if let Some(index) = font_index.filter(|_| self.options.count_font_hits) {
+                self.font_hits[index] += 1;
+            }
+            font_index
+        };
+        let options = &self.options;
+
+        if options.all_as_hex {
+            return (Rendering::Escaped(EscapeReason::All), font_index);
+        }
+
+        // The raw control bytes are kept, for example to be sent to a terminal again
+        if options.ascii_passthrough && char.is_ascii() {
+            return (Rendering::Literal, font_index);
+        }
+
+        let is_missing = self.is_missing(char, font_index);
+        if is_missing && !char.is_control() {
+            self.missing_chars.insert(char);
+        }
+
+        if options.collect_chars && !char.is_ascii() && !char.is_control() {
+            self.distinct_chars.insert(char);
+        }
+
+        if !options.escaped_ranges.is_empty() {
+            let rendering = match options
+                .escaped_ranges
+                .iter()
+                .any(|range| range.contains(&u32::from(char)))
+            {
+                true => Rendering::Escaped(EscapeReason::Range),
+                false => Rendering::Literal,
+            };
+            return (rendering, font_index);
+        }
+
+        let rendering = match char {
+            '\t' if options.tab_arrow => Rendering::Short("→", EscapeReason::Tab),
+            c if options.show_whitespace && chars::whitespace_symbol(c).is_some() => {
+                let reason = match c {
+                    '\n' => EscapeReason::Newline,
+                    '\r' => EscapeReason::CarriageReturn,
+                    '\t' => EscapeReason::Tab,
+                    ' ' => EscapeReason::Space,
+                    _ => EscapeReason::Whitespace,
+                };
+
+                Rendering::Short(chars::whitespace_symbol(c).unwrap(), reason)
+            }
+            '\n' if options.newline_escaped => Rendering::Short("\\n", EscapeReason::Newline),
+            '\n' if !options.newline_as_hex => Rendering::Literal,
+            '\r' if !options.carriage_return_as_hex => Rendering::Short("\\r", EscapeReason::CarriageReturn),
+            '\t' if !options.tab_as_hex => Rendering::Short("\\t", EscapeReason::Tab),
+            // Rust has no escapes for form feed and vertical tab
+            '\x0c' if !options.formfeed_as_hex && options.escape_style != EscapeStyle::Rust => {
+                Rendering::Short("\\f", EscapeReason::Whitespace)
+            }
+            '\x0b' if !options.vtab_as_hex && options.escape_style != EscapeStyle::Rust => {
+                Rendering::Short("\\v", EscapeReason::Whitespace)
+            }
+            // Octal escapes could continue \0 with the next characters
+            '\0' if options.base != NumericBase::Octal && options.escape_style != EscapeStyle::C => {
+                Rendering::Short("\\0", EscapeReason::Control)
+            }
+            ' ' if options.space_as_circle => Rendering::Glyph(options.space_glyph, EscapeReason::Space),
+            ' ' if options.space_as_hex => Rendering::Escaped(EscapeReason::Space),
+            '\n' => Rendering::Escaped(EscapeReason::Newline),
+            '\r' => Rendering::Escaped(EscapeReason::CarriageReturn),
+            '\t' => Rendering::Escaped(EscapeReason::Tab),
+            c if options.warn_bidi && chars::is_bidi_control(c) => {
+                self.bidi_controls.push((self.position, char));
+                Rendering::Escaped(EscapeReason::BidiControl)
+            }
+            c if c.is_control() => Rendering::Escaped(EscapeReason::Control),
+            c if c != ' ' && c.is_whitespace() => Rendering::Escaped(EscapeReason::Whitespace),
+            // The marker needs no glyph for the soft hyphen itself
+            '\u{ad}' if options.show_soft_hyphen => Rendering::Short("-·", EscapeReason::Invisible),
+            _ if is_missing => Rendering::Escaped(EscapeReason::MissingGlyph),
+            c if options.visible_invisibles && chars::is_invisible(c) => Rendering::Escaped(EscapeReason::Invisible),
+            _ => Rendering::Literal,
+        };
+
+        // Spaces are only escaped on request, as they can be rendered
+        if matches!(rendering, Rendering::Escaped(reason) if reason != EscapeReason::Space) {
+            self.unrenderable_chars.insert(char);
+        }
+
+        (rendering, font_index)
+    }
+
+    fn write_record(&mut self, original_bytes: &[u8], char: Option<char>, rendering: Rendering) -> io::Result<()> {
+        let rendered = matches!(rendering, Rendering::Literal);
+        if rendered && self.options.only_escaped {
+            return Ok(());
+        }
+
+        let record = json::CharRecord {
+            offset: self.position,
+            bytes: original_bytes,
+            char,
+            codepoint: char.map(u32::from),
+            rendered,
+            escaped_reason: match rendering {
+                Rendering::Literal => None,
+                Rendering::Short(_, reason) | Rendering::Glyph(_, reason) | Rendering::Escaped(reason) => {
+                    Some(reason.name())
+                }
+            },
+        };
+
+        if !self.options.json_lines {
+            let separator = if self.json_records == 0 { "[\n" } else { ",\n" };
+            write!(self.output, "{}", separator)?;
+        }
+
+        serde_json::to_writer(&mut self.output, &record)?;
+
+        if self.options.json_lines {
+            writeln!(self.output)?;
+        }
+
+        self.json_records += 1;
+        Ok(())
+    }
+
+    /// Writes the character as a value, or as its original bytes with [`FormatterOptions::as_bytes`].
+    fn write_escaped(&mut self, original_bytes: &[u8], char: char) -> io::Result<()> {
+        let name = if self.options.show_names || self.options.only_names {
+            unicode_names2::name(char)
+        } else {
+            None
+        };
+
+        if let Some(name) = name.as_ref().filter(|_| self.options.only_names) {
+            write!(self.output, "({})", name)?;
+        } else if self.options.escape_style == EscapeStyle::Html {
+            match chars::html_entity(char).filter(|_| self.options.html_named) {
+                Some(entity) => write!(self.output, "&{};", entity)?,
+                None => write!(self.output, "&#x{:x};", char as u32)?,
+            }
+        } else if self.options.escape_style == EscapeStyle::Rust && !(self.options.as_bytes && char.is_ascii()) {
+            // Byte escapes of string literals only go up to \x7f
+            write!(self.output, "\\u{{{:x}}}", char as u32)?;
+        } else if self.options.as_bytes {
+            for byte in original_bytes {
+                self.write_byte(*byte)?;
+            }
+        } else if self.options.escape_style == EscapeStyle::C {
+            // Universal character names cannot be used for ASCII
+            match char as u32 {
+                codepoint @ 0..=0x7f => write!(self.output, "\\{:03o}", codepoint)?,
+                codepoint @ 0x80..=0xffff => write!(self.output, "\\u{:04X}", codepoint)?,
+                codepoint => write!(self.output, "\\U{:08X}", codepoint)?,
+            }
+        } else {
+            let prefix = &self.options.unicode_prefix;
+            match self.options.base {
+                NumericBase::Hex => write!(self.output, "{}{{{:x}}}", prefix, char as u32)?,
+                NumericBase::Decimal => write!(self.output, "{}{{{}}}", prefix, char as u32)?,
+                NumericBase::Octal => write!(self.output, "{}{{{:o}}}", prefix, char as u32)?,
+                NumericBase::Binary => write!(self.output, "{}{{{:b}}}", prefix, char as u32)?,
+            }
+        }
+
+        if let Some(name) = name.filter(|_| !self.options.only_names) {
+            write!(self.output, "({})", name)?;
+        }
+
+        let category = unicode_general_category::get_general_category(char).abbreviation();
+        let block = unicode_blocks::find_unicode_block(char).map_or("No Block", |block| block.name());
+
+        match (self.options.show_category, self.options.show_block) {
+            (true, true) => write!(self.output, "[{}, {}]", category, block),
+            (true, false) => write!(self.output, "[{}]", category),
+            (false, true) => write!(self.output, "[{}]", block),
+            (false, false) => Ok(()),
+        }
+    }
+}
+
+impl Formatter<'_, Vec<u8>> {
+    /// Formats the whole input, and returns the original bytes of each character with the text written for it.
+    ///
+    /// The characters are formatted one at a time, so grapheme clusters and normalization do not span characters.
+    pub fn format_chars(&mut self, input: &[u8]) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let input = self.start_input(input);
+        let mut chars = Vec::new();
+        self.encoding.for_each_char(input, |bytes, _| chars.push(bytes));
+
+        let mut formatted = Vec::with_capacity(chars.len());
+        for bytes in chars {
+            self.process_sequential(bytes)?;
+            formatted.push((bytes.to_vec(), std::mem::take(&mut self.output.inner)));
+        }
+
+        // The text written at the end, like the start of an unfinished escape sequence, belongs to the last character
+        self.finish()?;
+        if let Some((_, text)) = formatted.last_mut() {
+            text.append(&mut self.output.inner);
+        }
+
+        self.output.inner.clear();
+        Ok(formatted)
+    }
+}
+
+/// The number of processed characters of each class.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CharSummary {
+    /// Characters that are not whitespace or control characters and have a glyph.
+    pub printable: usize,
+    pub whitespace: usize,
+    /// Control characters that are not whitespace.
+    pub control: usize,
+    /// Characters that have no glyph in any of the fonts.
+    pub missing_glyph: usize,
+    /// Bytes that are not valid in the encoding.
+    pub invalid_bytes: usize,
+}
+
+impl CharSummary {
+    fn add(&mut self, other: &CharSummary) {
+        self.printable += other.printable;
+        self.whitespace += other.whitespace;
+        self.control += other.control;
+        self.missing_glyph += other.missing_glyph;
+        self.invalid_bytes += other.invalid_bytes;
+    }
+}
+
+/// The counts of the processed text, like the ones of `wc`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub bytes: usize,
+    /// Valid characters, where invalid bytes do not count.
+    pub chars: usize,
+    /// New lines, like `wc -l`.
+    pub lines: usize,
+    /// Characters that are not printed as is, where a carriage return followed by a new line counts once.
+    pub escaped: usize,
+}
+
+/// Why a character is escaped instead of printed as is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EscapeReason {
+    /// The bytes are not valid in the encoding.
+    InvalidBytes,
+    /// Everything is escaped with [`FormatterOptions::all_as_hex`].
+    All,
+    Newline,
+    CarriageReturn,
+    Tab,
+    Space,
+    Control,
+    /// Whitespace other than a space.
+    Whitespace,
+    /// None of the fonts has a glyph for the character.
+    MissingGlyph,
+    BidiControl,
+    Invisible,
+    /// The character is in one of the [`FormatterOptions::escaped_ranges`].
+    Range,
+}
+
+impl EscapeReason {
+    pub fn name(self) -> &'static str {
+        match self {
+            EscapeReason::InvalidBytes => "invalid_bytes",
+            EscapeReason::All => "all",
+            EscapeReason::Newline => "newline",
+            EscapeReason::CarriageReturn => "carriage_return",
+            EscapeReason::Tab => "tab",
+            EscapeReason::Space => "space",
+            EscapeReason::Control => "control",
+            EscapeReason::Whitespace => "whitespace",
+            EscapeReason::MissingGlyph => "missing_glyph",
+            EscapeReason::BidiControl => "bidi_control",
+            EscapeReason::Invisible => "invisible",
+            EscapeReason::Range => "range",
+        }
+    }
+
+    /// Returns the ANSI color code to highlight the escaped characters with.
+    fn color(self) -> Option<&'static str> {
+        match self {
+            // Red for characters that are not text
+            EscapeReason::InvalidBytes | EscapeReason::Control | EscapeReason::BidiControl => Some("31"),
+            // Yellow for characters that the fonts cannot show
+            EscapeReason::MissingGlyph | EscapeReason::Invisible => Some("33"),
+            // Cyan for whitespace
+            EscapeReason::Newline
+            | EscapeReason::CarriageReturn
+            | EscapeReason::Tab
+            | EscapeReason::Space
+            | EscapeReason::Whitespace => Some("36"),
+            EscapeReason::All | EscapeReason::Range => None,
+        }
+    }
+}
+
+/// A Unicode normalization form, see <https://unicode.org/reports/tr15/>.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normalization {
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+}
+
+/// A part of the normalized buffer.
+enum Piece<'b> {
+    /// The range of valid characters in the normalized text.
+    Text(std::ops::Range<usize>),
+    Invalid(&'b [u8]),
+}
+
+/// Normalizes each run of valid characters, and keeps the invalid bytes between them.
+fn normalize(encoding: Encoding, buffer: &[u8], normalization: Normalization) -> (String, Vec<Piece<'_>>) {
+    let mut normalized = String::new();
+    let mut pieces = Vec::new();
+    let mut run = String::new();
+
+    let end_run = |run: &mut String, normalized: &mut String, pieces: &mut Vec<Piece>| {
+        if run.is_empty() {
+            return;
+        }
+
+        let start = normalized.len();
+        match normalization {
+            Normalization::Nfc => normalized.extend(run.nfc()),
+            Normalization::Nfd => normalized.extend(run.nfd()),
+            Normalization::Nfkc => normalized.extend(run.nfkc()),
+            Normalization::Nfkd => normalized.extend(run.nfkd()),
+        }
+
+        pieces.push(Piece::Text(start..normalized.len()));
+        run.clear();
+    };
+
+    encoding.for_each_char(buffer, |bytes, char| match char {
+        Some(char) => run.push(char),
+        None => {
+            end_run(&mut run, &mut normalized, &mut pieces);
+            pieces.push(Piece::Invalid(bytes));
+        }
+    });
+    end_run(&mut run, &mut normalized, &mut pieces);
+
+    (normalized, pieces)
+}
+
+/// Returns whether the ASCII character is never combined with other characters by normalization.
+///
+/// Letters take combining marks, and `<`, `=` and `>` combine with U+0338 like in `≠`.
+fn is_normalization_boundary(byte: u8) -> bool {
+    !byte.is_ascii_alphabetic() && !matches!(byte, b'<' | b'=' | b'>')
+}
+
+/// What to do with ANSI escape sequences, see <https://en.wikipedia.org/wiki/ANSI_escape_code>.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnsiMode {
+    /// Print each sequence as a token like `⟨SGR:31⟩`.
+    Label,
+    Strip,
+}
+
+/// The maximum length of an ANSI escape sequence, after which its characters are escaped as usual.
+const MAX_ANSI_SEQUENCE_LEN: usize = 64;
+
+/// How the characters of a grapheme cluster are printed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ClusterRendering {
+    /// Each character is printed as it would be on its own.
+    ByCharacter,
+    /// Every character is escaped, as the font cannot show the whole cluster.
+    Escaped,
+    /// The joiners are printed as is, so the font can render the joined sequence.
+    Joined,
+}
+
+#[derive(Clone, Copy)]
+enum Rendering {
+    /// Printed as is.
+    Literal,
+    /// Printed as a short escape sequence like `\n`.
+    Short(&'static str, EscapeReason),
+    /// Printed as a configured symbol, like the space glyph.
+    Glyph(char, EscapeReason),
+    /// Printed as a value.
+    Escaped(EscapeReason),
+}
+
+/// The smallest part of a buffer that is worth formatting on another thread.
+const MIN_PART_LEN: usize = 16 * 1024;
+
+/// Splits the buffer after newlines into at most `count` parts of about the same size.
+fn split_lines(buffer: &[u8], encoding: Encoding, count: usize) -> Vec<&[u8]> {
+    let part_len = buffer.len().div_ceil(count).max(MIN_PART_LEN);
+    let mut parts = Vec::with_capacity(count);
+    let mut rest = buffer;
+
+    while !rest.is_empty() {
+        let end = encoding.find_line_end(rest, part_len).unwrap_or(rest.len());
+        let (part, tail) = rest.split_at(end);
+        parts.push(part);
+        rest = tail;
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(options: FormatterOptions, parts: &[&[u8]]) -> String {
+        let mut formatter = Formatter::new(Vec::new(), &[], options);
+        for part in parts {
+            formatter.process_str(part).unwrap();
+        }
+        formatter.finish().unwrap();
+        String::from_utf8(formatter.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn octal_byte_values() {
+        let options = FormatterOptions {
+            base: NumericBase::Octal,
+            all_as_hex: true,
+            as_bytes: true,
+            no_font: true,
+            ..FormatterOptions::default()
+        };
+
+        assert_eq!(format(options.clone(), &[b"\x00"]), "\\000");
+        assert_eq!(format(options.clone(), &[b"\xff"]), "\\377");
+        assert_eq!(format(options, &[b"A"]), "\\101");
+    }
+
+    #[test]
+    fn escape_offsets_of_multibyte_chars() {
+        let options = FormatterOptions {
+            all_as_hex: true,
+            escape_offsets: true,
+            no_font: true,
+            ..FormatterOptions::default()
+        };
+        let expected = "@0:\\u{61}@1:\\u{4e2d}@4:\\u{1}";
+
+        assert_eq!(format(options.clone(), &["a\u{4e2d}\x01".as_bytes()]), expected);
+        // The character is completed by the next part, and keeps the offset of its first byte
+        assert_eq!(format(options.clone(), &[b"a\xe4", b"\xb8\xad\x01"]), expected);
+
+        // The bytes of a character are one escape, unless they are dumped
+        let options = FormatterOptions {
+            as_bytes: true,
+            ..options
+        };
+        let expected = "@0:\\x61@1:\\xe4\\xb8\\xad@4:\\x01";
+        assert_eq!(format(options.clone(), &["a\u{4e2d}\x01".as_bytes()]), expected);
+        assert_eq!(format(options.clone(), &[b"a\xe4", b"\xb8\xad\x01"]), expected);
+
+        let mut formatter = Formatter::new(Vec::new(), &[], options);
+        formatter.dump_bytes(b"a\xe4").unwrap();
+        formatter.dump_bytes(b"\xb8\xad\x01").unwrap();
+        formatter.finish().unwrap();
+        let output = String::from_utf8(formatter.into_inner()).unwrap();
+        assert_eq!(output, "@0:\\x61@1:\\xe4@2:\\xb8@3:\\xad@4:\\x01");
+    }
+
+    #[test]
+    fn c1_controls_without_font() {
+        let options = FormatterOptions {
+            no_font: true,
+            ..FormatterOptions::default()
+        };
+
+        for char in '\u{80}'..='\u{9f}' {
+            let text = format!("a{}b", char);
+            let expected = format!("a\\u{{{:x}}}b", u32::from(char));
+            assert_eq!(format(options.clone(), &[text.as_bytes()]), expected);
+        }
+        // Other characters of the Latin-1 block are printed as is
+        assert_eq!(format(options, &["\u{a1}".as_bytes()]), "\u{a1}");
+    }
+
+    #[test]
+    fn ruler_above_byte_values() {
+        let offsets = FormatterOptions {
+            offsets: true,
+            all_as_hex: true,
+            as_bytes: true,
+            ruler: true,
+            no_font: true,
+            ..FormatterOptions::default()
+        };
+        let grouped = FormatterOptions {
+            byte_group: 2,
+            byte_separator: String::from(" "),
+            ..offsets.clone()
+        };
+        let octal = FormatterOptions {
+            base: NumericBase::Octal,
+            ..offsets.clone()
+        };
+
+        for options in [offsets, grouped, octal] {
+            let mut formatter = Formatter::new(Vec::new(), &[], options);
+            formatter.dump_bytes(b"0123456789abcdef").unwrap();
+            formatter.finish().unwrap();
+            let output = String::from_utf8(formatter.into_inner()).unwrap();
+            let (ruler, row) = output.split_once('\n').unwrap();
+
+            // The last digit of each number is above the last digit of its value
+            let number_ends: Vec<usize> = ruler
+                .char_indices()
+                .filter(|&(index, char)| char != ' ' && !ruler[index + 1..].starts_with(|next| next != ' '))
+                .map(|(index, _)| index)
+                .collect();
+            let value_ends: Vec<usize> = row.match_indices('\\').map(|(index, _)| index + 3).collect();
+            assert_eq!(number_ends, value_ends, "{}", output);
+        }
+    }
+
+    #[test]
+    fn prefix_takes_columns() {
+        let options = FormatterOptions {
+            tab_arrow: true,
+            tab_width: 4,
+            wrap: Some(8),
+            no_font: true,
+            ..FormatterOptions::default()
+        };
+
+        let mut formatter = Formatter::new(Vec::new(), &[], options);
+        formatter.write_prefix("12345: ").unwrap();
+        formatter.process_str(b"a\tb").unwrap();
+        formatter.finish().unwrap();
+        let output = String::from_utf8(formatter.into_inner()).unwrap();
+        // The prefix and the `a` fill the line, so the tab starts the next one and takes all of its columns
+        assert_eq!(output, "12345: a\n\u{2192}   b");
+    }
+
+    #[test]
+    fn tab_arrow_with_zero_tab_width() {
+        let options = FormatterOptions {
+            tab_arrow: true,
+            tab_width: 0,
+            no_font: true,
+            ..FormatterOptions::default()
+        };
+
+        // The tab stops are one column apart, like in the width report
+        assert_eq!(format(options, &[b"a\tb"]), "a\u{2192}b");
+    }
+
+    #[test]
+    fn rust_escapes_of_non_ascii() {
+        let options = FormatterOptions {
+            escape_style: EscapeStyle::Rust,
+            all_as_hex: true,
+            no_font: true,
+            ..FormatterOptions::default()
+        };
+        let input: &[u8] = b"a\xff\xe4\xb8\xad";
+
+        assert_eq!(format(options.clone(), &[input]), "\\u{61}\\xff\\u{4e2d}");
+        // Only ASCII characters are escaped as bytes
+        let options = FormatterOptions {
+            as_bytes: true,
+            ..options
+        };
+        assert_eq!(format(options, &[input]), "\\x61\\xff\\u{4e2d}");
+    }
+
+    #[test]
+    fn record_delimiter_is_kept() {
+        let options = FormatterOptions {
+            no_font: true,
+            ..FormatterOptions::default()
+        };
+
+        let mut formatter = Formatter::new(Vec::new(), &[], options);
+        // Only the delimiter is kept, and the start of a character before it is invalid, as nothing continues it
+        formatter.process_str(b"a\0\xe4").unwrap();
+        formatter.end_record(b'\0').unwrap();
+        formatter.process_str(b"b").unwrap();
+        formatter.finish().unwrap();
+        let output = String::from_utf8(formatter.into_inner()).unwrap();
+        assert_eq!(output, "a\\0\\xe4\0b");
+    }
+}
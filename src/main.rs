@@ -1,101 +1,888 @@
 use std::{
-    io::{BufRead, IsTerminal, Read, StdoutLock, Write},
-    ops::Deref,
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Seek, SeekFrom, Write},
+    num::{NonZeroU32, NonZeroUsize},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    process::{Child, ExitCode, Stdio},
+    time::{Duration, Instant},
 };
 
-use ab_glyph::Font;
-use bstr::ByteSlice;
-use clap::{arg, crate_authors, crate_description, crate_name, crate_version, Arg, ArgAction, Command};
-
-struct Formatter<'a> {
-    stdout_lock: StdoutLock<'a>,
-    fontnames: &'a str,
-    as_bytes: bool,
-    all_as_hex: bool,
-    hex_as_decimal: bool,
-    newline_escaped: bool,
-    newline_as_hex: bool,
-    carriage_return_as_hex: bool,
-    tab_as_hex: bool,
-    space_as_circle: bool,
-    space_as_hex: bool,
-}
-
-impl<'a> Formatter<'a> {
-    fn write_byte(&mut self, byte: u8) {
-        if self.hex_as_decimal {
-            write!(self.stdout_lock, "\\d{:03}", byte).unwrap();
-        } else {
-            write!(self.stdout_lock, "\\x{:02x}", byte).unwrap();
+use buffer::SharedBuffer;
+use clap::{
+    arg, builder::NonEmptyStringValueParser, crate_authors, crate_description, crate_name, crate_version,
+    parser::ValueSource, value_parser, Arg, ArgAction, ArgGroup, ArgMatches, Command,
+};
+use decompress::Decompress;
+use detect::Detection;
+use encode::{decode_base64, decode_hex_tokens, Encoder, TextEncoding};
+use hexv::{
+    decode, default_font_family, get_font_source, load_font, load_font_cached, load_font_file, load_font_file_cached,
+    match_font_family, similar_font_names, AnsiMode, ByteStats, CharSummary, Counts, Encoding, EscapeStyle, FontCow,
+    FontMatch, FontProperties, Formatter, FormatterOptions, Normalization, NumericBase,
+};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+mod buffer;
+mod config;
+mod decompress;
+mod detect;
+mod diff;
+mod encode;
+#[cfg(feature = "render")]
+mod render;
+#[cfg(feature = "tui")]
+mod tui;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(exit_code) => exit_code,
+        // The reader of the output, like `head`, does not need the rest of it
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: Could not write the output: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs the program, where only errors of writing the output are returned.
+fn run() -> io::Result<ExitCode> {
+    // Parse args, where the options of the config file are the defaults
+    let matches = match config::apply(command()) {
+        Ok(command) => command.get_matches(),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
         }
+    };
+
+    if matches.get_flag("features") {
+        list_features()?;
+        return Ok(ExitCode::SUCCESS);
     }
 
-    fn write_char(&mut self, char: char) {
-        if self.as_bytes {
-            for byte in char.to_string().bytes() {
-                self.write_byte(byte);
+    if let Some(matches) = matches.subcommand_matches("list-fonts") {
+        list_fonts(matches.get_flag("verbose"))?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let quiet = matches.get_flag("quiet");
+    let mut font_files: Vec<PathBuf> = matches.get_many("font-file").unwrap_or_default().cloned().collect();
+    for dir in matches.get_many::<PathBuf>("font-dir").unwrap_or_default() {
+        match font_files_in(dir) {
+            Ok(paths) if paths.is_empty() && !quiet => eprintln!("Warning: No font files found in '{}'", dir.display()),
+            Ok(paths) => font_files.extend(paths),
+            Err(err) => {
+                eprintln!("Error: Could not read font directory '{}': {}", dir.display(), err);
+                std::process::exit(1);
+            }
+        }
+    }
+    let fonts_file = matches
+        .get_one::<PathBuf>("fonts-file")
+        .map(|path| match read_font_list(path) {
+            Ok(fontnames) if fontnames.is_empty() && !quiet => {
+                eprintln!("Warning: No font names found in '{}'", path.display());
+                fontnames
+            }
+            Ok(fontnames) => fontnames,
+            Err(err) => {
+                eprintln!("Error: Could not read fonts file '{}': {}", path.display(), err);
+                std::process::exit(1);
+            }
+        });
+    // HEXV_FONTS and then the fonts of the config file are only defaults, so they are not used together with the font
+    // files of the arguments
+    let fontnames = match matches.get_one::<String>("fontname") {
+        _ if fonts_file.is_some() => fonts_file.filter(|fontnames| !fontnames.is_empty()),
+        Some(fontnames) if matches.value_source("fontname") == Some(ValueSource::CommandLine) => {
+            Some(fontnames.clone())
+        }
+        _ if !font_files.is_empty() => None,
+        config_fontnames => std::env::var("HEXV_FONTS")
+            .ok()
+            .filter(|fontnames| !fontnames.is_empty())
+            .or_else(|| config_fontnames.cloned()),
+    };
+    let mut fontnames: Vec<String> = match fontnames {
+        Some(fontnames) => fontnames.split(',').map(String::from).collect(),
+        None => Vec::new(),
+    };
+    let offsets = matches.get_flag("offsets");
+    let canonical = matches.get_flag("canonical");
+    let raw_hex = matches.get_flag("raw-hex");
+    let escape_style = if matches.get_flag("html") {
+        EscapeStyle::Html
+    } else if matches.get_flag("rust") {
+        EscapeStyle::Rust
+    } else if matches.get_flag("c") {
+        EscapeStyle::C
+    } else {
+        EscapeStyle::Default
+    };
+    let highlights: Vec<Highlight> = matches.get_many("highlight").unwrap_or_default().copied().collect();
+    let verbose = matches.get_count("verbose");
+    let endian = matches.get_one::<String>("endian").map(String::as_str);
+    let mut options = FormatterOptions {
+        as_bytes: matches.get_flag("bytes") || offsets || canonical || raw_hex,
+        all_as_hex: matches.get_flag("all") || offsets || canonical || raw_hex,
+        base: if matches.get_flag("decimal") {
+            NumericBase::Decimal
+        } else if matches.get_flag("octal") {
+            NumericBase::Octal
+        } else if matches.get_flag("binary") {
+            NumericBase::Binary
+        } else {
+            NumericBase::Hex
+        },
+        // String literals cannot contain a new line
+        newline_escaped: matches.get_flag("newline-escaped")
+            || (matches!(escape_style, EscapeStyle::Rust | EscapeStyle::C) && !matches.get_flag("newline-hex")),
+        newline_as_hex: matches.get_flag("newline-hex") && !matches.get_flag("newline-escaped"),
+        carriage_return_as_hex: matches.get_flag("carriage-return-hex"),
+        crlf: matches.get_flag("crlf"),
+        tab_as_hex: matches.get_flag("tab-hex"),
+        formfeed_as_hex: matches.get_flag("formfeed-hex"),
+        vtab_as_hex: matches.get_flag("vtab-hex"),
+        tab_arrow: matches.get_flag("tab-arrow"),
+        tab_width: matches.get_one::<NonZeroUsize>("tab-width").unwrap().get(),
+        space_as_circle: matches.get_flag("space-circle"),
+        space_glyph: *matches.get_one::<char>("space-glyph").unwrap(),
+        space_as_hex: matches.get_flag("space-hex") && !matches.get_flag("space-circle"),
+        show_whitespace: matches.get_flag("show-whitespace"),
+        byte_prefix: match raw_hex {
+            true => String::new(),
+            false => prefix_arg(&matches, "byte-prefix"),
+        },
+        decimal_prefix: prefix_arg(&matches, "decimal-prefix"),
+        octal_prefix: prefix_arg(&matches, "octal-prefix"),
+        binary_prefix: prefix_arg(&matches, "binary-prefix"),
+        group_nibbles: matches.get_flag("group-nibbles"),
+        uppercase: matches.get_flag("uppercase"),
+        // Groups are separated by a space, unless there is another separator
+        byte_separator: match matches.get_one::<String>("byte-separator") {
+            Some(separator) => separator.clone(),
+            None if matches.contains_id("group") => String::from(" "),
+            None => String::new(),
+        },
+        byte_group: matches.get_one::<NonZeroUsize>("group").map_or(1, |group| group.get()),
+        reverse_groups: endian == Some("little") && matches.contains_id("group"),
+        escape_style,
+        html_named: matches.get_flag("html-named"),
+        unicode_prefix: prefix_arg(&matches, "unicode-prefix"),
+        offsets,
+        columns: matches.get_one::<NonZeroUsize>("columns").unwrap().get(),
+        raw_hex_columns: match matches.value_source("columns") {
+            Some(ValueSource::CommandLine) if raw_hex => {
+                Some(matches.get_one::<NonZeroUsize>("columns").unwrap().get())
             }
-        } else if self.hex_as_decimal {
-            write!(self.stdout_lock, "\\u{{{}}}", char as u32).unwrap();
+            _ => None,
+        },
+        escape_offsets: matches.get_flag("with-offsets"),
+        canonical,
+        ruler: matches.get_flag("ruler"),
+        panel_font_check: matches.get_flag("panel-fonts"),
+        only_escaped: matches.get_flag("only-escaped"),
+        show_font: matches.get_flag("show-font"),
+        collect_chars: matches.get_flag("coverage-matrix"),
+        count_font_hits: matches.get_flag("report-unused-fonts"),
+        no_font: matches.get_flag("no-font"),
+        encoding: match matches.get_one::<String>("encoding").map(String::as_str) {
+            Some("utf16") if endian == Some("big") => Encoding::Utf16Be,
+            Some("utf16") | Some("utf16le") => Encoding::Utf16Le,
+            Some("utf16be") => Encoding::Utf16Be,
+            Some("latin1") => Encoding::Latin1,
+            Some("windows-1252") => Encoding::Windows1252,
+            Some("auto") => Encoding::Auto,
+            _ => Encoding::Utf8,
+        },
+        strip_bom: matches.get_flag("strip-bom"),
+        show_names: matches.get_flag("names"),
+        only_names: matches.get_flag("only-names"),
+        show_category: matches.get_flag("category"),
+        show_block: matches.get_flag("block"),
+        visible_invisibles: matches.get_flag("visible-invisibles"),
+        show_soft_hyphen: matches.get_flag("show-soft-hyphen"),
+        confusables: matches.get_flag("confusables"),
+        json: matches.get_flag("json"),
+        json_lines: matches.get_flag("json") && matches.get_flag("line-by-line"),
+        summary: matches.get_flag("summary"),
+        count: matches.get_flag("count"),
+        explain: matches.get_flag("explain"),
+        normalization: match matches.get_one::<String>("normalize").map(String::as_str) {
+            Some("nfc") => Some(Normalization::Nfc),
+            Some("nfd") => Some(Normalization::Nfd),
+            Some("nfkc") => Some(Normalization::Nfkc),
+            Some("nfkd") => Some(Normalization::Nfkd),
+            _ => None,
+        },
+        graphemes: matches.get_flag("graphemes"),
+        width_report: matches.get_flag("width-report"),
+        replace_invalid: matches.get_flag("replace-invalid"),
+        collapse_invalid: matches
+            .get_one::<NonZeroUsize>("collapse-invalid")
+            .map(|min_len| min_len.get()),
+        ansi: if matches.get_flag("strip-ansi") {
+            Some(AnsiMode::Strip)
+        } else if matches.get_flag("ansi") {
+            Some(AnsiMode::Label)
         } else {
-            write!(self.stdout_lock, "{}", char.escape_unicode()).unwrap();
+            None
+        },
+        // Depends on the output, which is opened later
+        color: false,
+        replacements: matches
+            .get_many::<(char, String)>("replace")
+            .map(|replacements| replacements.cloned().collect())
+            .unwrap_or_default(),
+        warn_bidi: matches.get_flag("warn-bidi") || matches.get_flag("deny-bidi"),
+        highlighted_bytes: highlights
+            .iter()
+            .filter_map(|highlight| match highlight {
+                Highlight::Byte(byte) => Some(*byte),
+                Highlight::Char(_) => None,
+            })
+            .collect(),
+        highlighted_chars: highlights
+            .iter()
+            .filter_map(|highlight| match highlight {
+                Highlight::Char(char) => Some(*char),
+                Highlight::Byte(_) => None,
+            })
+            .collect(),
+        escaped_ranges: matches.get_many("range").unwrap_or_default().cloned().collect(),
+        ascii_passthrough: matches.get_flag("ascii-passthrough"),
+        always_rendered: matches.get_many("always-render").unwrap_or_default().copied().collect(),
+        never_rendered: matches.get_many("never-render").unwrap_or_default().copied().collect(),
+        log_glyphs: verbose > 1,
+        wrap: None,
+        wrap_indent: *matches.get_one::<usize>("wrap-indent").unwrap(),
+    };
+    // How each line is processed, if the input is read by lines
+    let lines = matches.get_flag("line-by-line").then(|| LineSplit {
+        delimiter: match matches.get_flag("null-data") {
+            true => b'\0',
+            false => matches.get_one::<u8>("delimiter").copied().unwrap_or(b'\n'),
+        },
+        max_len: matches.get_one::<u64>("max-line").copied().unwrap_or(u64::MAX),
+    });
+    let skip = *matches.get_one::<u64>("skip").unwrap();
+    let length = matches.get_one::<u64>("length").copied();
+    // The number of the next line, which continues over the files
+    let mut line_number = matches
+        .get_flag("line-numbers")
+        .then(|| *matches.get_one::<usize>("start-line").unwrap());
+
+    let encoding = match matches.get_one::<String>("encode").map(String::as_str) {
+        Some("base64") => Some(TextEncoding::Base64),
+        Some("base32") => Some(TextEncoding::Base32),
+        Some("hex") => Some(TextEncoding::Hex),
+        _ => None,
+    };
+    let mode = if matches.get_flag("decode") {
+        Mode::Decode
+    } else if matches.get_flag("decode-base64") {
+        Mode::DecodeBase64
+    } else if let Some(encoding) = encoding {
+        Mode::Encode(encoding)
+    } else if matches.get_flag("stats") {
+        Mode::Stats
+    } else if options.all_as_hex && options.as_bytes && !options.json {
+        Mode::DumpBytes
+    } else {
+        Mode::Format
+    };
+
+    let mut timings = Timings {
+        enabled: matches.get_flag("timings"),
+        ..Timings::default()
+    };
+    let fonts_start = timings.start();
+
+    // Init font database
+    let mut font_db = fontdb::Database::new();
+
+    // Load fonts
+    let render = matches.subcommand_matches("render");
+    if render.is_some() && options.no_font {
+        eprintln!("Error: render cannot be used with --no-font");
+        std::process::exit(1);
+    }
+    let needs_fonts = render.is_some()
+        || (!options.no_font && (mode == Mode::Format || (mode == Mode::DumpBytes && options.panel_font_check)));
+    if needs_fonts && fontnames.is_empty() && font_files.is_empty() {
+        font_db.load_system_fonts();
+
+        match default_font_family(&font_db) {
+            Some(family) => fontnames.push(family),
+            None => {
+                eprintln!("Error: No installed font found, use --font-file to load one");
+                std::process::exit(1);
+            }
         }
+    } else if needs_fonts && !fontnames.is_empty() {
+        font_db.load_system_fonts();
     }
 
-    fn process_str(&mut self, fonts: &[FontCow], buffer: &[u8]) {
-        for (start, end, char) in buffer.char_indices() {
-            let char_as_string = char.to_string();
-            let original_bytes = &buffer[start..end];
-            let new_bytes = char_as_string.as_bytes();
+    let font_properties = FontProperties {
+        weight: matches.get_one("font-weight").copied().unwrap_or_default(),
+        style: match matches.get_one::<String>("font-style").map(String::as_str) {
+            Some("italic") => fontdb::Style::Italic,
+            Some("oblique") => fontdb::Style::Oblique,
+            _ => fontdb::Style::Normal,
+        },
+        stretch: match matches.get_one::<String>("font-stretch").map(String::as_str) {
+            Some("ultra-condensed") => fontdb::Stretch::UltraCondensed,
+            Some("extra-condensed") => fontdb::Stretch::ExtraCondensed,
+            Some("condensed") => fontdb::Stretch::Condensed,
+            Some("semi-condensed") => fontdb::Stretch::SemiCondensed,
+            Some("semi-expanded") => fontdb::Stretch::SemiExpanded,
+            Some("expanded") => fontdb::Stretch::Expanded,
+            Some("extra-expanded") => fontdb::Stretch::ExtraExpanded,
+            Some("ultra-expanded") => fontdb::Stretch::UltraExpanded,
+            _ => fontdb::Stretch::Normal,
+        },
+    };
+
+    let font_match = match matches.get_one::<String>("font-match").map(String::as_str) {
+        Some("substring") => FontMatch::Substring,
+        Some("fuzzy") => FontMatch::Fuzzy,
+        _ => FontMatch::Exact,
+    };
+
+    // Skip fonts that are not installed, as long as any other font is available
+    let mut font_sources = Vec::with_capacity(fontnames.len());
+    if needs_fonts {
+        for fontname in fontnames {
+            let family = match_font_family(&font_db, &fontname, font_match);
+            if let Some(family) = family.as_ref().filter(|family| verbose > 0 && **family != fontname) {
+                eprintln!("Debug: Font '{}' matched the family '{}'", fontname, family);
+            }
+
+            match family.and_then(|family| Some((get_font_source(&font_db, &family, &font_properties)?, family))) {
+                Some(((src, index), family)) => {
+                    if verbose > 0 {
+                        eprintln!(
+                            "Debug: Font '{}' resolved to {} (index {})",
+                            family,
+                            describe_source(&src),
+                            index
+                        );
+                    }
 
-            if original_bytes != new_bytes {
-                for byte in original_bytes {
-                    self.write_byte(*byte);
+                    font_sources.push((family, src, index));
                 }
+                None if quiet => {}
+                None => {
+                    eprintln!("Warning: Font '{}' not found", fontname);
 
-                continue;
+                    let similar = similar_font_names(&font_db, &fontname, 3);
+                    if !similar.is_empty() {
+                        eprintln!("  Similar fonts: {}", similar.join(", "));
+                    }
+                }
             }
+        }
+
+        if font_sources.is_empty() && font_files.is_empty() {
+            eprintln!("Error: None of the fonts were found");
+            std::process::exit(1);
+        }
+    }
 
-            if self.all_as_hex {
-                self.write_char(char);
-                continue;
+    let mut fonts = Vec::with_capacity(font_sources.len() + font_files.len());
+    // The cached coverage of the fonts has no glyphs to draw
+    let no_cache = matches.get_flag("no-cache") || render.is_some();
+    for (fontname, src, index) in &font_sources {
+        let font = if no_cache {
+            load_font(src, *index)
+        } else {
+            load_font_cached(src, *index)
+        };
+
+        match font {
+            Ok(font) => {
+                if verbose > 0 {
+                    log_loaded_font(fontname, &font);
+                }
+
+                fonts.push(font);
             }
+            Err(err) => {
+                eprintln!("Error: Could not load font '{}': {}", fontname, err);
+                std::process::exit(1);
+            }
+        }
+    }
 
-            match char {
-                c if c == '\n' && self.newline_escaped => write!(self.stdout_lock, "\\n").unwrap(),
-                c if c == '\n' && !self.newline_as_hex => write!(self.stdout_lock, "{}", char).unwrap(),
-                c if c == '\r' && !self.carriage_return_as_hex => write!(self.stdout_lock, "\\r").unwrap(),
-                c if c == '\t' && !self.tab_as_hex => write!(self.stdout_lock, "\\t").unwrap(),
-                c if c == ' ' && self.space_as_circle => write!(self.stdout_lock, "🞄").unwrap(),
-                c if c.is_ascii_control()
-                    || (c != ' ' && c.is_whitespace())
-                    || (c == ' ' && self.space_as_hex)
-                    || (!c.is_ascii() && !is_char_in_fonts(fonts, char)) =>
-                {
-                    self.write_char(char);
+    if needs_fonts {
+        for path in &font_files {
+            let font = if no_cache {
+                load_font_file(path)
+            } else {
+                load_font_file_cached(path)
+            };
+            let font = match font {
+                Ok(font) => font,
+                Err(err) => {
+                    eprintln!("Error: Could not load font file '{}': {}", path.display(), err);
+                    std::process::exit(1);
                 }
-                _ => write!(self.stdout_lock, "{}", char).unwrap(),
             };
+
+            if verbose > 0 {
+                log_loaded_font(&path.display().to_string(), &font);
+            }
+
+            fonts.push(font);
         }
     }
-}
 
-fn is_char_in_fonts(fonts: &[FontCow], char: char) -> bool {
-    for font in fonts {
-        if font.glyph_id(char).0 != 0 {
-            return true;
+    timings.fonts = elapsed(fonts_start);
+
+    if let Some(render) = render {
+        return render_chars(render, &fonts);
+    }
+
+    // Prepare output, where the output for the clipboard is kept until it is complete
+    let check = matches.get_flag("check");
+    let clipboard = matches.get_flag("to-clipboard").then(SharedBuffer::default);
+    let (output, output_is_terminal): (Box<dyn Write>, bool) = match (&clipboard, matches.get_one::<String>("output")) {
+        (Some(clipboard), _) => (Box::new(clipboard.clone()), false),
+        (None, Some(path)) => match File::create(path) {
+            Ok(file) => (Box::new(BufWriter::new(file)), false),
+            Err(err) => {
+                eprintln!("Error: Could not create '{}': {}", path, err);
+                std::process::exit(1);
+            }
+        },
+        (None, None) => {
+            let stdout = std::io::stdout().lock();
+            let is_terminal = stdout.is_terminal();
+            (Box::new(BufWriter::new(stdout)), is_terminal)
+        }
+    };
+    let mut flusher = Flusher {
+        flush: match matches.get_one::<String>("flush").map(String::as_str) {
+            Some("line") => Flush::Line,
+            Some("never") => Flush::Never,
+            Some("interval") => Flush::Interval,
+            _ if output_is_terminal => Flush::Line,
+            _ => Flush::Never,
+        },
+        last: Instant::now(),
+    };
+
+    // The pager writes to the terminal instead, so the output is still treated as one
+    let mut pager = if matches.get_flag("pager") && output_is_terminal {
+        spawn_pager(quiet)
+    } else {
+        None
+    };
+    let output: Box<dyn Write> = match pager.as_mut().and_then(|pager| pager.stdin.take()) {
+        Some(stdin) => Box::new(BufWriter::new(stdin)),
+        None => output,
+    };
+
+    // The view shows the output once all input is formatted
+    #[cfg(feature = "tui")]
+    let view = matches.get_flag("tui").then(SharedBuffer::default);
+    #[cfg(feature = "tui")]
+    let output: Box<dyn Write> = match &view {
+        Some(_) if !output_is_terminal => {
+            eprintln!("Error: --tui needs a terminal");
+            std::process::exit(1);
+        }
+        Some(view) => Box::new(view.clone()),
+        None => output,
+    };
+    #[cfg(not(feature = "tui"))]
+    if matches.get_flag("tui") {
+        eprintln!("Error: hexv was built without the tui feature");
+        std::process::exit(1);
+    }
+    #[cfg(not(feature = "clipboard"))]
+    if matches.get_flag("from-clipboard") || matches.get_flag("to-clipboard") {
+        eprintln!("Error: hexv was built without the clipboard feature");
+        std::process::exit(1);
+    }
+
+    let decompress = match matches.get_one::<String>("decompress").map(String::as_str) {
+        Some("auto") => Some(Decompress::Auto),
+        Some("gzip") => Some(Decompress::Gzip),
+        Some("zstd") => Some(Decompress::Zstd),
+        _ => None,
+    };
+    #[cfg(not(feature = "compression"))]
+    if decompress.is_some() {
+        eprintln!("Error: hexv was built without the compression feature");
+        std::process::exit(1);
+    }
+
+    // Colors are only for terminals, see https://no-color.org
+    options.color = match matches.get_one::<String>("color").map(String::as_str) {
+        Some("always") => true,
+        Some("never") => false,
+        _ => output_is_terminal && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+    };
+
+    // Without a number of columns, the lines are only wrapped at the width of a terminal
+    options.wrap = match matches.get_one::<NonZeroUsize>("wrap") {
+        Some(columns) => Some(columns.get()),
+        None if matches.contains_id("wrap") && output_is_terminal => {
+            terminal_size::terminal_size().map(|(terminal_size::Width(width), _)| usize::from(width))
+        }
+        None => None,
+    };
+
+    // Reports are written after all input is processed, instead of the formatted text
+    let missing_report = matches.get_flag("missing-report");
+    let coverage_matrix = matches.get_flag("coverage-matrix");
+    let detect_only = matches.get_flag("detect-only");
+    let (output, mut report_output): (Box<dyn Write>, _) = if check || missing_report || coverage_matrix || detect_only
+    {
+        (Box::new(std::io::sink()), Some(output))
+    } else {
+        (output, None)
+    };
+
+    let font_names: Vec<String> = font_sources
+        .iter()
+        .map(|(fontname, _, _)| fontname.clone())
+        .chain(font_files.iter().map(|path| match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => path.display().to_string(),
+        }))
+        .collect();
+    let mut formatter = Formatter::new(output, &fonts, options)
+        .with_font_names(font_names.clone())
+        .with_glyph_log(Box::new(io::stderr()))
+        .with_jobs(matches.get_one::<NonZeroUsize>("jobs").unwrap().get());
+
+    // The files of the diff are compared instead of formatted one after the other
+    let diff = matches.subcommand_matches("diff");
+    #[cfg(feature = "clipboard")]
+    let clipboard_text = matches.get_flag("from-clipboard").then(read_clipboard);
+    #[cfg(not(feature = "clipboard"))]
+    let clipboard_text: Option<String> = None;
+    let text = match (matches.get_one::<String>("expr"), &clipboard_text) {
+        (Some(expr), _) => Some(("--expr", expr.as_str())),
+        (None, Some(clipboard_text)) => Some(("the clipboard", clipboard_text.as_str())),
+        (None, None) => None,
+    };
+    let paths: Vec<&str> = match matches.get_many::<String>("file") {
+        _ if diff.is_some() => Vec::new(),
+        // The text of --expr or the clipboard takes the place of standard input
+        _ if text.is_some() => vec!["-"],
+        Some(paths) => paths.map(String::as_str).collect(),
+        None => vec!["-"],
+    };
+    let recursive = matches.get_flag("recursive");
+    let headers = matches.get_flag("headers") || recursive;
+    let fail_fast = matches.get_flag("fail-fast");
+    let report_bom = matches.get_flag("report-bom");
+    let input_hex = matches.get_flag("input-hex");
+    let detect_encoding = matches.get_flag("detect-encoding") || detect_only;
+    // A detected encoding is only used if no encoding is given
+    let use_detected = matches.value_source("encoding") != Some(ValueSource::CommandLine);
+    let deny_bidi = matches.get_flag("deny-bidi");
+    let mut failed = false;
+    let mut unreadable = false;
+    let mut read_any = false;
+    let mut buffer = Vec::with_capacity(256);
+    let mut stats = ByteStats::default();
+
+    // The directories are replaced by the files in them
+    let walked: Vec<String>;
+    let paths = if recursive {
+        let globs: Vec<glob::Pattern> = matches.get_many("glob").unwrap_or_default().cloned().collect();
+        let mut visited = BTreeSet::new();
+        let mut files = Vec::new();
+
+        for path in paths {
+            let dir = Path::new(path);
+            let complete = match dir.is_dir() {
+                true if matches.get_flag("respect-gitignore") => walk_dir_ignored(dir, &globs, &mut files),
+                true => walk_dir(dir, dir, &globs, &mut visited, &mut files),
+                false => {
+                    files.push(path.to_string());
+                    true
+                }
+            };
+            unreadable |= !complete;
+        }
+
+        walked = files;
+        walked.iter().map(String::as_str).collect()
+    } else {
+        paths
+    };
+
+    if let Some(diff) = diff {
+        let paths = ["old", "new"].map(|id| diff.get_one::<String>(id).unwrap().as_str());
+        failed = diff_files(&mut formatter, &fonts, &font_names, paths, skip, length, decompress)?;
+    }
+
+    for (index, path) in paths.iter().enumerate() {
+        if headers {
+            if index > 0 {
+                formatter.write_prefix("\n")?;
+            }
+
+            let name = if *path == "-" { "standard input" } else { path };
+            formatter.write_prefix(&format!("==> {} <==\n", name))?;
+        }
+
+        buffer.clear();
+        let name = match text {
+            Some((name, _)) => name,
+            None if *path == "-" => "standard input",
+            None => path,
+        };
+
+        let open_start = timings.start();
+        let input = match text {
+            Some((_, text)) => Ok(text_input(text, skip, length)),
+            None => open_input(path, lines.is_none(), skip, length, decompress),
+        };
+        timings.reading += elapsed(open_start);
+        let result = input.map_err(InputError::Read).and_then(|input| {
+            let input = match input_hex {
+                true => hex_input(input).map_err(InputError::Read)?,
+                false => input,
+            };
+            let input = match detect_encoding {
+                true => {
+                    let (detection, input) = detect_input(input).map_err(InputError::Read)?;
+                    report_detection(&mut formatter, name, &detection, use_detected, quiet);
+                    input
+                }
+                false => input,
+            };
+
+            if detect_only {
+                return Ok(());
+            }
+
+            formatter.set_start_offset(skip as usize);
+            process_input(
+                &mut formatter,
+                &mut stats,
+                input,
+                &mut buffer,
+                mode,
+                lines,
+                line_number.as_mut(),
+                &mut flusher,
+                &mut timings,
+            )
+        });
+
+        if let Err(err) = result {
+            let err = match err {
+                InputError::Read(err) => err,
+                InputError::Write(err) => return Err(err),
+            };
+
+            eprintln!("Error: Could not read '{}': {}", name, err);
+            unreadable = true;
+
+            if fail_fast {
+                break;
+            }
+        } else {
+            read_any = true;
+
+            if let Some(bom) = formatter.bom().filter(|_| report_bom) {
+                eprintln!("'{}' starts with a {} byte order mark", name, bom);
+            }
+
+            for (position, char) in formatter.bidi_controls() {
+                let char_name = unicode_names2::name(*char)
+                    .map(|name| name.to_string())
+                    .unwrap_or_default();
+                eprintln!(
+                    "Warning: '{}' contains the bidirectional control character U+{:04X} {} at byte {}",
+                    name, *char as u32, char_name, position
+                );
+            }
+
+            if deny_bidi && !formatter.bidi_controls().is_empty() {
+                failed = true;
+            }
+        }
+    }
+
+    // Files that cannot be read are only a failure with --quiet if no file could be read
+    if unreadable && !(quiet && read_any) {
+        failed = true;
+    }
+
+    // Final newline, which auto only adds for terminal output if there is no ending newline
+    let options = formatter.options();
+    let final_newline = if matches.get_flag("no-trailing-newline") {
+        "never"
+    } else if matches.get_flag("ensure-trailing-newline") {
+        "always"
+    } else {
+        matches.get_one::<String>("final-newline").unwrap().as_str()
+    };
+    let trailing_newline = match final_newline {
+        "never" => false,
+        // The encoded text always ends with a newline
+        "always" => !formatter.ends_with_newline() && !matches!(mode, Mode::Stats | Mode::Encode(_)) && diff.is_none(),
+        _ => {
+            (options.all_as_hex || options.newline_escaped || options.newline_as_hex || options.only_escaped)
+                && !options.offsets
+                && !options.canonical
+                && !options.json
+                && !matches!(mode, Mode::Stats | Mode::Encode(_))
+                && diff.is_none()
+                && output_is_terminal
+        }
+    };
+    if trailing_newline {
+        writeln!(formatter.get_mut())?;
+    }
+
+    if mode == Mode::Stats {
+        write_stats(formatter.get_mut(), &stats, *matches.get_one::<usize>("top").unwrap())?;
+    }
+
+    formatter.get_mut().flush()?;
+
+    if let Some(mut pager) = pager {
+        // Closing its input lets the pager know that the output is complete
+        *formatter.get_mut() = Box::new(std::io::sink());
+        let _ = pager.wait();
+    }
+
+    #[cfg(feature = "tui")]
+    if let Some(view) = view {
+        if let Err(err) = tui::run(&view.0.borrow()) {
+            eprintln!("Error: Could not show the view: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(output) = report_output.as_mut() {
+        if missing_report {
+            write_missing_report(output, formatter.missing_chars())?;
+        }
+
+        if coverage_matrix {
+            write_coverage_matrix(output, &fonts, &font_names, formatter.distinct_chars())?;
+        }
+
+        output.flush()?;
+    }
+
+    #[cfg(feature = "clipboard")]
+    if let Some(clipboard) = clipboard {
+        write_clipboard(String::from_utf8_lossy(&clipboard.0.borrow()).into_owned());
+    }
+
+    if formatter.options().confusables && formatter.confusable_count() > 0 {
+        eprintln!(
+            "Found {} characters that can be mistaken for ASCII",
+            formatter.confusable_count()
+        );
+    }
+
+    if formatter.options().summary {
+        print_summary(formatter.summary());
+    }
+
+    if formatter.options().count {
+        print_counts(formatter.counts());
+    }
+
+    if formatter.options().count_font_hits {
+        print_unused_fonts(&font_names, formatter.font_hits());
+    }
+
+    if check && formatter.found_unrenderable() {
+        if !quiet {
+            print_check_summary(&formatter);
         }
+
+        failed = true;
     }
 
-    false
+    if timings.enabled {
+        print_timings(&timings);
+    }
+
+    Ok(if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS })
 }
 
-fn main() {
-    // Parse args
-    let matches = Command::new(crate_name!())
+/// Returns the command line arguments without the defaults of the config file.
+fn command() -> Command {
+    Command::new(crate_name!())
         .version(crate_version!())
         .author(crate_authors!())
         .about(crate_description!())
+        .subcommand_negates_reqs(true)
+        .arg(
+            arg!(
+                --"features" "Print the version and which optional features this build has"
+            )
+            .exclusive(true)
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .subcommand(
+            Command::new("list-fonts")
+                .about("List the installed font families")
+                .arg(
+                    arg!(
+                        -v --"verbose" "Also print the style, weight and file of each font"
+                    )
+                    .required(false)
+                    .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("render")
+                .about("Draw the glyphs of the characters into a PNG image, where the missing ones are crossed out")
+                .arg(
+                    Arg::new("text")
+                        .value_name("TEXT")
+                        .help("The characters to draw, where each is only drawn once [default: standard input]"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Writes the image to this file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("size")
+                        .long("size")
+                        .value_name("PX")
+                        .help("Sets the height of the glyphs in pixels")
+                        .value_parser(value_parser!(NonZeroU32))
+                        .default_value("48"),
+                )
+                .arg(
+                    Arg::new("columns")
+                        .long("columns")
+                        .value_name("N")
+                        .help("Sets the number of glyphs per row")
+                        .value_parser(value_parser!(NonZeroUsize))
+                        .default_value("16"),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Print the lines in which two files differ, with the differing characters marked")
+                .arg(Arg::new("old").value_name("FILE1").required(true))
+                .arg(Arg::new("new").value_name("FILE2").required(true)),
+        )
         .arg(
             arg!(
                 -b --"bytes" "Show bytes instead of unicode values"
@@ -117,6 +904,66 @@ fn main() {
             .required(false)
             .action(ArgAction::SetTrue),
         )
+        .arg(
+            arg!(
+                --"octal" "Print hex values as octal values"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"binary" "Print hex values as binary values"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .group(
+            ArgGroup::new("base")
+                .args(["decimal", "octal", "binary"])
+                .multiple(false),
+        )
+        .arg(
+            arg!(
+                --"html" "Print escaped characters as HTML entities"
+            )
+            .conflicts_with_all(["base", "bytes", "json"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"rust" "Print escaped characters as escapes of Rust string literals, where invalid bytes and the bytes of -a --bytes are escapes of byte string literals b\"…\""
+            )
+            .conflicts_with_all(["base", "json"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"c" "Print escaped characters as escapes of C string literals"
+            )
+            .conflicts_with_all(["base", "json"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .group(ArgGroup::new("style").args(["html", "rust", "c"]).multiple(false))
+        .arg(
+            arg!(
+                --"html-named" "Prefer named HTML entities like &nbsp; where available"
+            )
+            .requires("html")
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"group-nibbles" "Separate the nibbles of binary values with an underscore"
+            )
+            .requires("binary")
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
         .arg(
             arg!(
                 -n --"newline-escaped" "Print new line as \\n"
@@ -141,172 +988,2038 @@ fn main() {
         )
         .arg(
             arg!(
-                -t --"tab-hex" "Print tab as hex value instead of \\t"
+                --"crlf" "Print carriage return followed by new line as ⏎, or as \\r\\n with --newline-escaped"
             )
             .required(false)
             .action(ArgAction::SetTrue),
         )
         .arg(
             arg!(
-                -s --"space-circle" "Print space as circle (🞄)"
+                -t --"tab-hex" "Print tab as hex value instead of \\t"
             )
             .required(false)
             .action(ArgAction::SetTrue),
         )
         .arg(
             arg!(
-                -S --"space-hex" "Print space as hex value"
+                --"formfeed-hex" "Print form feed as hex value instead of \\f"
             )
-            .conflicts_with("space-circle")
             .required(false)
             .action(ArgAction::SetTrue),
         )
         .arg(
-            Arg::new("fontname")
-                .short('f')
-                .long("fontname")
-                .value_name("FONT1[,FONT2,...]")
-                .help("Sets the font to check whether a glyph is present")
-                .required(true),
+            arg!(
+                --"vtab-hex" "Print vertical tab as hex value instead of \\v"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
         )
         .arg(
             arg!(
-                -l --"line-by-line" "Read lines by line"
+                -s --"space-circle" "Print space as circle (🞄)"
             )
             .required(false)
             .action(ArgAction::SetTrue),
         )
-        .get_matches();
-
-    let stdout = std::io::stdout();
-
-    let mut formatter = Formatter {
-        stdout_lock: stdout.lock(),
-        fontnames: matches.get_one::<String>("fontname").unwrap(),
-        as_bytes: matches.get_flag("bytes"),
-        all_as_hex: matches.get_flag("all"),
-        hex_as_decimal: matches.get_flag("decimal"),
-        newline_escaped: matches.get_flag("newline-escaped"),
-        newline_as_hex: matches.get_flag("newline-hex") && !matches.get_flag("newline-escaped"),
-        carriage_return_as_hex: matches.get_flag("carriage-return-hex"),
-        tab_as_hex: matches.get_flag("tab-hex"),
-        space_as_circle: matches.get_flag("space-circle"),
-        space_as_hex: matches.get_flag("space-hex") && !matches.get_flag("space-circle"),
-    };
-    let line_by_line = matches.get_flag("line-by-line");
-
-    // Prepare to read text from stdin
-    let stdin = std::io::stdin();
-    let mut stdin = stdin.lock();
-    let mut buffer = Vec::with_capacity(256);
-
-    if formatter.all_as_hex && formatter.as_bytes {
-        if line_by_line {
-            loop {
-                let read = stdin.read_until(b'\n', &mut buffer).unwrap();
+        .arg(
+            Arg::new("space-glyph")
+                .long("space-glyph")
+                .value_name("CHAR")
+                .help("Sets the symbol for space with --space-circle, like · or ␣")
+                .value_parser(parse_char)
+                .default_value("🞄")
+                .requires("space-circle"),
+        )
+        .arg(
+            Arg::new("replace")
+                .long("replace")
+                .value_name("CHAR=TEXT")
+                .help("Prints the character as the text, where both may contain escapes like \\t, \\n or \\u{a0}")
+                .value_parser(parse_replacement)
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("highlight")
+                .long("highlight")
+                .value_name("VALUE")
+                .help("Marks a byte like \\xff wherever it is printed as a value, or a character like U+FEFF")
+                .value_parser(parse_highlight)
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("always-render")
+                .long("always-render")
+                .value_name("U+XXXX,...")
+                .help("Prints these characters as is, as if the fonts had a glyph for them")
+                .value_parser(parse_code_point)
+                .value_delimiter(',')
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("never-render")
+                .long("never-render")
+                .value_name("U+XXXX,...")
+                .help("Escapes these characters, as if the fonts had no glyph for them")
+                .value_parser(parse_code_point)
+                .value_delimiter(',')
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("range")
+                .long("range")
+                .value_name("START-END")
+                .help("Escapes only the characters between two hex code points like 0400-04FF, and prints all others as is")
+                .value_parser(parse_range)
+                .action(ArgAction::Append)
+                .conflicts_with_all(["all", "offsets", "canonical"]),
+        )
+        .arg(
+            arg!(
+                --"ascii-passthrough" "Print all ASCII characters as is, even control characters, and escape only the others"
+            )
+            .conflicts_with_all(["all", "offsets", "canonical", "raw-hex", "range", "crlf", "ansi"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                -S --"space-hex" "Print space as hex value"
+            )
+            .conflicts_with("space-circle")
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"tab-arrow" "Print tab as → padded with spaces to the next tab stop"
+            )
+            .conflicts_with("tab-hex")
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("tab-width")
+                .long("tab-width")
+                .value_name("N")
+                .help("Sets the number of columns between tab stops, for --tab-arrow, --wrap and --width-report")
+                .value_parser(value_parser!(NonZeroUsize))
+                .default_value("8"),
+        )
+        .arg(
+            arg!(
+                --"show-whitespace" "Print whitespace as symbols, like · for space, → for tab and ¶ for new line"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("fontname")
+                .short('f')
+                .long("fontname")
+                .value_name("FONT1[,FONT2,...]")
+                .help("Sets the font to check whether a glyph is present [default: HEXV_FONTS, the config file or the sans-serif font]")
+                .required(false),
+        )
+        .arg(
+            Arg::new("fonts-file")
+                .long("fonts-file")
+                .value_name("PATH")
+                .help("Reads the fonts of --fontname from a file, one per line or separated by commas")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with("fontname"),
+        )
+        .arg(
+            Arg::new("font-match")
+                .long("font-match")
+                .value_name("MODE")
+                .help("Sets how font names are matched with the installed families, substring and fuzzy ignore the case and pick the best match")
+                .value_parser(["exact", "substring", "fuzzy"])
+                .default_value("exact"),
+        )
+        .arg(
+            Arg::new("font-file")
+                .short('F')
+                .long("font-file")
+                .value_name("PATH")
+                .help("Loads a TrueType, OpenType or WOFF2 font file to check whether a glyph is present")
+                .value_parser(value_parser!(PathBuf))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("font-dir")
+                .long("font-dir")
+                .value_name("PATH")
+                .help("Loads the .ttf, .otf, .ttc and .woff2 font files in a directory, like with --font-file")
+                .value_parser(value_parser!(PathBuf))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(
+                --"no-font" "Print every character except control characters and whitespace as is, without loading fonts"
+            )
+            .conflicts_with_all([
+                "fontname",
+                "fonts-file",
+                "font-file",
+                "font-dir",
+                "show-font",
+                "report-unused-fonts",
+                "panel-fonts",
+                "missing-report",
+                "coverage-matrix",
+            ])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("font-weight")
+                .long("font-weight")
+                .value_name("WEIGHT")
+                .help("Sets the weight of the fonts, as a number from 1 to 1000 or a name like bold")
+                .value_parser(parse_font_weight),
+        )
+        .arg(
+            Arg::new("font-style")
+                .long("font-style")
+                .value_name("STYLE")
+                .help("Sets the style of the fonts")
+                .value_parser(["normal", "italic", "oblique"]),
+        )
+        .arg(
+            Arg::new("font-stretch")
+                .long("font-stretch")
+                .value_name("STRETCH")
+                .help("Sets the stretch of the fonts")
+                .value_parser([
+                    "ultra-condensed",
+                    "extra-condensed",
+                    "condensed",
+                    "semi-condensed",
+                    "normal",
+                    "semi-expanded",
+                    "expanded",
+                    "extra-expanded",
+                    "ultra-expanded",
+                ]),
+        )
+        .arg(
+            arg!(
+                --"no-cache" "Do not use the cached glyph coverage of the fonts"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("byte-prefix")
+                .long("byte-prefix")
+                .value_name("STR")
+                .help("Sets the prefix of hex byte values")
+                .value_parser(NonEmptyStringValueParser::new())
+                .default_value("\\x"),
+        )
+        .arg(
+            Arg::new("decimal-prefix")
+                .long("decimal-prefix")
+                .value_name("STR")
+                .help("Sets the prefix of decimal byte values")
+                .value_parser(NonEmptyStringValueParser::new())
+                .default_value("\\d"),
+        )
+        .arg(
+            Arg::new("octal-prefix")
+                .long("octal-prefix")
+                .value_name("STR")
+                .help("Sets the prefix of octal byte values")
+                .value_parser(NonEmptyStringValueParser::new())
+                .default_value("\\"),
+        )
+        .arg(
+            Arg::new("binary-prefix")
+                .long("binary-prefix")
+                .value_name("STR")
+                .help("Sets the prefix of binary byte values")
+                .value_parser(NonEmptyStringValueParser::new())
+                .default_value("\\b"),
+        )
+        .arg(
+            Arg::new("byte-separator")
+                .long("byte-separator")
+                .value_name("STR")
+                .help("Writes this text between byte values that are printed one after the other, like a space")
+                .required(false),
+        )
+        .arg(
+            Arg::new("group")
+                .long("group")
+                .value_name("N")
+                .help("Separates the byte values in groups of N, with a space or the byte separator")
+                .value_parser(value_parser!(NonZeroUsize))
+                .conflicts_with("canonical")
+                .required(false),
+        )
+        .arg(
+            Arg::new("endian")
+                .long("endian")
+                .value_name("ORDER")
+                .help("Sets the byte order of --encoding utf16 [default: little], and with little writes each --group in reverse")
+                .value_parser(["big", "little"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("unicode-prefix")
+                .long("unicode-prefix")
+                .value_name("STR")
+                .help("Sets the prefix of unicode values")
+                .value_parser(NonEmptyStringValueParser::new())
+                .default_value("\\u"),
+        )
+        .arg(
+            arg!(
+                --"offsets" "Print bytes in rows with their offset (implies --all --bytes)"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                -C --"canonical" "Print bytes like hexdump -C with offsets and a character panel"
+            )
+            .conflicts_with("offsets")
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"panel-fonts" "Show the characters of the panel based on the fonts instead of ASCII"
+            )
+            .requires("canonical")
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"with-offsets" "Prefix each escape with the byte offset of its character, like @12:\\xff"
+            )
+            .conflicts_with_all(["offsets", "canonical", "json"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"raw-hex" "Print every byte as bare hex digits without a prefix, in rows of --columns if given"
+            )
+            .conflicts_with_all(["base", "style", "json", "byte-prefix"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"uppercase" "Print the hex digits of byte values in uppercase"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .group(ArgGroup::new("rows").args(["offsets", "canonical"]).multiple(false))
+        // The rows of --columns bytes
+        .group(
+            ArgGroup::new("byte-rows")
+                .args(["offsets", "canonical", "raw-hex"])
+                .multiple(false),
+        )
+        .arg(
+            arg!(
+                --"ruler" "Print the number of each column above the rows of --offsets or --canonical"
+            )
+            .requires("rows")
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("columns")
+                .long("columns")
+                .value_name("N")
+                .help("Sets the number of bytes per row")
+                .value_parser(value_parser!(NonZeroUsize))
+                .default_value("16")
+                .requires("byte-rows"),
+        )
+        .arg(
+            arg!(
+                --"decode" "Turn escaped output back into the original bytes"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"input-hex" "Read the input as hex tokens like 48 65 6c or 0x48,0x65, and format the bytes they stand for"
+            )
+            .conflicts_with_all(["skip", "length", "decode", "decode-base64"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("encode")
+                .long("encode")
+                .value_name("ENCODING")
+                .help("Prints the whole input in the encoding instead of formatting its characters, wrapped with --wrap")
+                .value_parser(["base64", "base32", "hex"])
+                .conflicts_with_all([
+                    "decode",
+                    "stats",
+                    "json",
+                    "offsets",
+                    "canonical",
+                    "raw-hex",
+                    "check",
+                    "missing-report",
+                    "line-numbers",
+                ]),
+        )
+        .arg(
+            arg!(
+                --"decode-base64" "Turn base64 text, like the output of --encode base64, back into the original bytes"
+            )
+            .conflicts_with_all(["decode", "encode", "stats", "json", "offsets", "canonical", "check", "missing-report"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"only-escaped" "Print only the escaped characters"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"show-font" "Print the name of the font that has the glyph after each non-ASCII character"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"check" "Print nothing and exit with 1 if anything has to be escaped"
+            )
+            .conflicts_with_all(["decode", "all", "offsets", "canonical", "output"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"missing-report" "Print only the distinct characters that have no glyph in the fonts"
+            )
+            .conflicts_with_all(["decode", "all", "offsets", "canonical", "check"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                -q --"quiet" "Do not print warnings or list the characters found by --check, and only fail for files that cannot be read if none can be read"
+            )
+            .conflicts_with("verbose")
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("color")
+                .long("color")
+                .value_name("WHEN")
+                .help("Highlights escaped characters by their kind, auto only does on terminals without NO_COLOR")
+                .value_parser(["auto", "always", "never"])
+                .default_value("auto"),
+        )
+        .arg(
+            Arg::new("normalize")
+                .long("normalize")
+                .value_name("FORM")
+                .help("Normalizes the text before it is checked, so the offsets no longer match the input")
+                .value_parser(["nfc", "nfd", "nfkc", "nfkd"])
+                .conflicts_with_all(["offsets", "canonical"]),
+        )
+        .arg(
+            arg!(
+                --"coverage-matrix" "Print a table of which fonts have a glyph for each distinct non-ASCII character"
+            )
+            .conflicts_with_all(["decode", "all", "offsets", "canonical", "check", "missing-report"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"graphemes" "Escape grapheme clusters like emoji with modifiers as a whole if a glyph of them is missing, and keep the joiners of emoji sequences"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"width-report" "Print the display width of each line instead of the text, where wide characters take two columns"
+            )
+            .conflicts_with_all(["decode", "offsets", "canonical", "json", "stats", "check", "missing-report"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"replace-invalid" "Print each invalid sequence as one replacement character instead of its bytes"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("collapse-invalid")
+                .long("collapse-invalid")
+                .value_name("N")
+                .help("Prints a run of at least N invalid bytes as one token, like ⟨12 invalid bytes⟩")
+                .value_parser(value_parser!(NonZeroUsize))
+                .conflicts_with_all(["offsets", "canonical", "json", "width-report"]),
+        )
+        .arg(
+            arg!(
+                --"ansi" "Print ANSI escape sequences like colors as one token, like ⟨SGR:31⟩"
+            )
+            .conflicts_with("json")
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"strip-ansi" "Remove ANSI escape sequences like colors"
+            )
+            .conflicts_with_all(["json", "ansi"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("encoding")
+                .long("encoding")
+                .value_name("ENCODING")
+                .help("Sets the encoding of the input, auto detects UTF-16 by its byte order mark")
+                .value_parser(["utf8", "utf16", "utf16le", "utf16be", "latin1", "windows-1252", "auto"])
+                .default_value("utf8"),
+        )
+        .arg(
+            arg!(
+                --"detect-encoding" "Detect the encoding of each file, print it to stderr and read the file with it unless --encoding is given"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"detect-only" "Only print the detected encoding of each file, without the formatted text"
+            )
+            .conflicts_with_all(["check", "missing-report", "coverage-matrix", "stats", "decode"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"names" "Print the Unicode name after each escaped character"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"only-names" "Print the Unicode name instead of the value of escaped characters"
+            )
+            .conflicts_with("names")
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"category" "Print the Unicode general category after each escaped character"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"block" "Print the Unicode block after each escaped character"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"warn-bidi" "Always escape bidirectional control characters and warn about them on stderr"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"deny-bidi" "Like --warn-bidi, but also exit with 1 if any are found"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"visible-invisibles" "Escape zero-width and other invisible characters even if the font has them"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"show-soft-hyphen" "Print soft hyphens as -·, even if the font has them"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"confusables" "Mark characters that can be mistaken for ASCII and count them on stderr"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"json" "Print a JSON record for each character, one per line with --line-by-line"
+            )
+            .conflicts_with_all(["decode", "offsets", "canonical", "check", "missing-report", "headers"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"stats" "Print the frequency of the bytes and their entropy instead of the text"
+            )
+            .conflicts_with_all([
+                "decode",
+                "offsets",
+                "canonical",
+                "check",
+                "missing-report",
+                "json",
+                "headers",
+            ])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("top")
+                .long("top")
+                .value_name("N")
+                .help("Sets the number of most frequent bytes printed by --stats")
+                .value_parser(value_parser!(usize))
+                .default_value("10")
+                .requires("stats"),
+        )
+        .arg(
+            arg!(
+                --"summary" "Print the number of printable, whitespace, control and missing characters to stderr"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"explain" "Print a line for each character that tells how it is printed and why, instead of the text"
+            )
+            .conflicts_with_all([
+                "json",
+                "width-report",
+                "offsets",
+                "canonical",
+                "raw-hex",
+                "stats",
+                "decode",
+                "decode-base64",
+                "encode",
+                "wrap",
+                "collapse-invalid",
+                "ansi",
+                "crlf",
+                "with-offsets",
+            ])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"report-unused-fonts" "Print the fonts that were never the first to have a glyph for a character to stderr"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"count" "Print the number of lines, characters, bytes and escaped characters to stderr, like wc"
+            )
+            .conflicts_with_all(["decode", "decode-base64", "encode", "stats", "offsets", "canonical", "raw-hex"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"strip-bom" "Skip a byte order mark at the start of each file"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"report-bom" "Print the byte order mark at the start of each file to stderr"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                -l --"line-by-line" "Read lines by line"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                -z --"null-data" "Read lines that end with NUL instead of new line, like the output of find -print0, and keep the NUL at their end"
+            )
+            .requires("line-by-line")
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("delimiter")
+                .long("delimiter")
+                .value_name("BYTE")
+                .help("Sets the byte that ends each line, as a single character or an escape like \\x1e")
+                .value_parser(parse_delimiter)
+                .requires("line-by-line")
+                .conflicts_with("null-data"),
+        )
+        .arg(
+            Arg::new("max-line")
+                .long("max-line")
+                .value_name("BYTES")
+                .help("Processes lines longer than BYTES in parts, so they do not have to fit into memory")
+                .value_parser(parse_max_line)
+                .requires("line-by-line"),
+        )
+        .arg(
+            Arg::new("flush")
+                .long("flush")
+                .value_name("WHEN")
+                .help("Flushes the output after each line, at most every 100 ms with interval, or only at the end; line on terminals")
+                .value_parser(["line", "never", "interval"]),
+        )
+        .arg(
+            arg!(
+                --"line-numbers" "Print the number of each line before it"
+            )
+            .requires("line-by-line")
+            .conflicts_with_all([
+                "decode",
+                "offsets",
+                "canonical",
+                "json",
+                "stats",
+                "check",
+                "missing-report",
+            ])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("start-line")
+                .long("start-line")
+                .value_name("N")
+                .help("Sets the number of the first line")
+                .value_parser(value_parser!(usize))
+                .default_value("1")
+                .requires("line-numbers"),
+        )
+        .arg(
+            Arg::new("wrap")
+                .long("wrap")
+                .value_name("COLS")
+                .help("Breaks lines wider than COLS columns, or than the terminal without a value")
+                .value_parser(value_parser!(NonZeroUsize))
+                .num_args(0..=1)
+                .require_equals(true)
+                .conflicts_with_all(["offsets", "canonical", "json", "width-report"]),
+        )
+        .arg(
+            Arg::new("wrap-indent")
+                .long("wrap-indent")
+                .value_name("N")
+                .help("Sets the number of spaces before the continuation of a wrapped line")
+                .value_parser(value_parser!(usize))
+                .default_value("0")
+                .requires("wrap"),
+        )
+        .arg(
+            arg!(
+                --"timings" "Print how long loading the fonts, reading and formatting took to standard error"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_name("N")
+                .help("Sets the number of threads that format the lines of large files")
+                .value_parser(value_parser!(NonZeroUsize))
+                .default_value("1")
+                .conflicts_with("line-by-line"),
+        )
+        .arg(
+            Arg::new("skip")
+                .long("skip")
+                .value_name("N")
+                .help("Skips the first N bytes of each file, where N may be hex with 0x")
+                .value_parser(parse_size)
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("length")
+                .long("length")
+                .value_name("N")
+                .help("Stops after N bytes of each file, where N may be hex with 0x")
+                .value_parser(parse_size),
+        )
+        .arg(
+            arg!(
+                --"pager" "Show the output in a pager if it is a terminal, which is HEXV_PAGER, PAGER or less"
+            )
+            .conflicts_with_all(["output", "check", "missing-report", "tui"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"tui" "Show the output in a view that can be scrolled and searched, if built with the tui feature"
+            )
+            .conflicts_with_all(["output", "check", "missing-report", "line-by-line"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("file")
+                .value_name("FILE")
+                .help("The files to read, or - for standard input")
+                .required(false)
+                .num_args(1..),
+        )
+        .arg(
+            Arg::new("expr")
+                .short('e')
+                .long("expr")
+                .value_name("STRING")
+                .help("Reads the text of this argument instead of the files or standard input")
+                .conflicts_with_all(["file", "headers", "recursive"])
+                .required(false),
+        )
+        .arg(
+            arg!(
+                --"from-clipboard" "Read the text on the clipboard instead of the files or standard input, if built with the clipboard feature"
+            )
+            .conflicts_with_all(["file", "headers", "recursive", "expr"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"to-clipboard" "Put the output on the clipboard instead of standard output, if built with the clipboard feature"
+            )
+            .conflicts_with_all(["output", "pager", "tui"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"headers" "Print a header with the file name before each file"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                -R --"recursive" "Read the files in directories and their subdirectories, each with a header"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"respect-gitignore" "Skip the paths in directories that are ignored by .gitignore files"
+            )
+            .requires("recursive")
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("glob")
+                .long("glob")
+                .value_name("PATTERN")
+                .help("Only reads the files in directories whose name or path matches the pattern, like *.rs")
+                .value_parser(|value: &str| glob::Pattern::new(value).map_err(|err| err.to_string()))
+                .action(ArgAction::Append)
+                .requires("recursive"),
+        )
+        .arg(
+            Arg::new("final-newline")
+                .long("final-newline")
+                .value_name("WHEN")
+                .help("Adds a new line at the end of the output if it does not end with one, auto only does on terminals if new lines are escaped")
+                .value_parser(["auto", "always", "never"])
+                .default_value("auto"),
+        )
+        .arg(
+            arg!(
+                --"no-trailing-newline" "Same as --final-newline never"
+            )
+            .conflicts_with("final-newline")
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"ensure-trailing-newline" "Same as --final-newline always"
+            )
+            .conflicts_with_all(["no-trailing-newline", "final-newline"])
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Write the output to a file instead of standard output")
+                .required(false),
+        )
+        .arg(
+            Arg::new("decompress")
+                .long("decompress")
+                .value_name("FORMAT")
+                .help("Sets the compression of the input, where auto detects it by the extension or the first bytes, if built with the compression feature")
+                .value_parser(["auto", "gzip", "zstd", "none"])
+                .default_value("none"),
+        )
+        .arg(
+            arg!(
+                --"fail-fast" "Stop at the first file that cannot be read"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                -v --"verbose" "Print which font each font name resolved to and how it was loaded to standard error, and with -vv the glyph of each looked up character"
+            )
+            .required(false)
+            .action(ArgAction::Count),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("PATH")
+                .help("Reads the default flags and fonts from this file instead of hexv/config.toml in the config directory, where --no-<flag> turns off one of its flags")
+                .value_parser(value_parser!(PathBuf))
+                .required(false),
+        )
+        .arg(
+            arg!(
+                --"no-config" "Ignore the config file"
+            )
+            .conflicts_with("config")
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+}
+
+/// Returns the path of a font source for the log of --verbose.
+fn describe_source(source: &fontdb::Source) -> String {
+    match source {
+        fontdb::Source::File(path) | fontdb::Source::SharedFile(path, _) => path.display().to_string(),
+        fontdb::Source::Binary(_) => String::from("a font in memory"),
+    }
+}
+
+fn log_loaded_font(name: &str, font: &FontCow) {
+    match font.glyph_count() {
+        Some(count) => eprintln!("Debug: Loaded '{}' with {} glyphs", name, count),
+        None => eprintln!("Debug: Loaded the cached coverage of '{}'", name),
+    }
+}
+
+/// Returns the text on the clipboard, and exits if it cannot be read.
+#[cfg(feature = "clipboard")]
+fn read_clipboard() -> String {
+    match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text()) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Error: Could not read the clipboard: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Replaces the text on the clipboard, and exits if it cannot be written.
+///
+/// On Linux, the text only stays on the clipboard after hexv exits if a clipboard manager takes it over.
+#[cfg(feature = "clipboard")]
+fn write_clipboard(text: String) {
+    if let Err(err) = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text)) {
+        eprintln!("Error: Could not write the clipboard: {}", err);
+        std::process::exit(1);
+    }
+}
+
+/// Starts the pager of the environment with piped input, returns `None` if it cannot be started.
+fn spawn_pager(quiet: bool) -> Option<Child> {
+    let command = ["HEXV_PAGER", "PAGER"]
+        .into_iter()
+        .find_map(|name| std::env::var(name).ok().filter(|command| !command.trim().is_empty()))
+        .unwrap_or_else(|| String::from("less"));
+    let mut args = command.split_whitespace();
+    let program = args.next()?;
+
+    let mut pager = std::process::Command::new(program);
+    pager.args(args).stdin(Stdio::piped());
+
+    // Like git, let less keep the colors and quit if the output fits on the screen
+    if std::env::var_os("LESS").is_none() {
+        pager.env("LESS", "FRX");
+    }
+
+    match pager.spawn() {
+        Ok(child) => Some(child),
+        Err(err) => {
+            if !quiet {
+                eprintln!("Warning: Could not start the pager '{}': {}", program, err);
+            }
+
+            None
+        }
+    }
+}
+
+fn write_stats(output: &mut dyn Write, stats: &ByteStats, top: usize) -> io::Result<()> {
+    let total = stats.total();
+    writeln!(output, "Bytes: {}", total)?;
+    writeln!(output, "Distinct bytes: {}", stats.most_frequent(256).len())?;
+    writeln!(output, "Entropy: {:.3} bits per byte", stats.entropy())?;
+    writeln!(output, "Looks like: {}", stats.kind())?;
+
+    if total == 0 || top == 0 {
+        return Ok(());
+    }
+
+    writeln!(output, "Most frequent bytes:")?;
+    for (byte, count) in stats.most_frequent(top) {
+        let char = if byte.is_ascii_graphic() || byte == b' ' {
+            format!("'{}'", char::from(byte))
+        } else {
+            String::new()
+        };
+
+        writeln!(
+            output,
+            "  0x{:02x} {:<3} {:>12} {:>6.2}%",
+            byte,
+            char,
+            count,
+            count as f64 * 100.0 / total as f64
+        )?;
+    }
+
+    Ok(())
+}
+
+fn write_missing_report(output: &mut dyn Write, chars: &BTreeSet<char>) -> io::Result<()> {
+    for char in chars {
+        writeln!(output, "U+{:04X} {}", *char as u32, char)?;
+    }
 
-                if read == 0 {
-                    break;
-                }
+    Ok(())
+}
 
-                for byte in &buffer[..read] {
-                    formatter.write_byte(*byte);
-                }
+/// Writes a table of which fonts have a glyph for each character, and how many characters each font has.
+fn write_coverage_matrix(
+    output: &mut dyn Write,
+    fonts: &[FontCow],
+    font_names: &[String],
+    chars: &BTreeSet<char>,
+) -> io::Result<()> {
+    let mut line = String::from("Char  Codepoint");
+    for name in font_names {
+        line.push_str("  ");
+        line.push_str(name);
+    }
+    writeln!(output, "{}", line)?;
+
+    for char in chars {
+        // Wide characters like CJK take two of the columns
+        let padding = 6usize.saturating_sub(char.width().unwrap_or(0));
+        let mut line = format!("{}{:padding$}{:<9}", char, "", format!("U+{:04X}", *char as u32));
+
+        for (font, name) in fonts.iter().zip(font_names) {
+            let mark = if font.has_glyph(*char) { "✓" } else { "✗" };
+            line.push_str(&format!("  {:<1$}", mark, name.width()));
+        }
+
+        writeln!(output, "{}", line.trim_end())?;
+    }
+
+    let mut line = format!("{:<15}", "Total");
+    for (font, name) in fonts.iter().zip(font_names) {
+        let covered = chars.iter().filter(|char| font.has_glyph(**char)).count();
+        line.push_str(&format!(
+            "  {:<1$}",
+            format!("{}/{}", covered, chars.len()),
+            name.width()
+        ));
+    }
+    writeln!(output, "{}", line.trim_end())
+}
+
+fn print_summary(summary: &CharSummary) {
+    eprintln!("Printable characters:  {}", summary.printable);
+    eprintln!("Whitespace characters: {}", summary.whitespace);
+    eprintln!("Control characters:    {}", summary.control);
+    eprintln!("Missing glyphs:        {}", summary.missing_glyph);
+    eprintln!("Invalid bytes:         {}", summary.invalid_bytes);
+}
+
+fn print_counts(counts: &Counts) {
+    eprintln!(
+        "{:>7} lines {:>7} characters {:>7} bytes {:>7} escaped",
+        counts.lines, counts.chars, counts.bytes, counts.escaped
+    );
+}
+
+/// Prints the fonts that were never the first to have a glyph for a character, so they can be left out.
+fn print_unused_fonts(font_names: &[String], font_hits: &[usize]) {
+    let unused: Vec<&String> = font_names
+        .iter()
+        .zip(font_hits)
+        .filter(|(_, hits)| **hits == 0)
+        .map(|(name, _)| name)
+        .collect();
+
+    if unused.is_empty() {
+        eprintln!("Every font was the first to have a glyph for a character");
+    }
+
+    for name in unused {
+        eprintln!("Font '{}' was never the first to have a glyph for a character", name);
+    }
+}
+
+fn print_check_summary<W: Write>(formatter: &Formatter<W>) {
+    let chars = formatter.unrenderable_chars();
+    let bytes = formatter.invalid_bytes();
+    eprintln!(
+        "Found {} characters that cannot be rendered:",
+        chars.len() + bytes.len()
+    );
+
+    for char in chars {
+        eprintln!("U+{:04X}", *char as u32);
+    }
+
+    for byte in bytes {
+        eprintln!("0x{:02X} (invalid UTF-8)", byte);
+    }
+}
+
+/// Reads the font names in the file, which are separated by new lines or commas, as one list like the one of
+/// `--fontname`.
+///
+/// Lines that start with `#` are comments.
+fn read_font_list(path: &Path) -> io::Result<String> {
+    let text = std::fs::read_to_string(path)?;
+    let fontnames: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .flat_map(|line| line.split(','))
+        .map(str::trim)
+        .filter(|fontname| !fontname.is_empty())
+        .collect();
+
+    Ok(fontnames.join(","))
+}
+
+/// Returns the font files in the directory, sorted by their name, without those in its subdirectories.
+fn font_files_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
 
-                formatter.stdout_lock.flush().unwrap();
-                buffer.clear();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_font = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| ["ttf", "otf", "ttc", "woff2"].contains(&extension.to_lowercase().as_str()));
+
+        if is_font && path.is_file() {
+            paths.push(path);
+        }
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
+/// Adds the files in the directory and its subdirectories that match any of the globs to `files`, sorted by their
+/// path, and returns false if a directory could not be read.
+///
+/// The globs are matched with the path of a file relative to `root`, or with its name. A directory that is reached
+/// again, like through a symbolic link to one of its parents, is skipped.
+fn walk_dir(
+    root: &Path,
+    dir: &Path,
+    globs: &[glob::Pattern],
+    visited: &mut BTreeSet<PathBuf>,
+    files: &mut Vec<String>,
+) -> bool {
+    if let Ok(canonical) = dir.canonicalize() {
+        if !visited.insert(canonical) {
+            return true;
+        }
+    }
+
+    let entries = std::fs::read_dir(dir).and_then(|entries| {
+        entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()
+    });
+    let mut paths = match entries {
+        Ok(paths) => paths,
+        Err(err) => {
+            eprintln!("Error: Could not read '{}': {}", dir.display(), err);
+            return false;
+        }
+    };
+    paths.sort();
+
+    let mut complete = true;
+    for path in paths {
+        if path.is_dir() {
+            complete &= walk_dir(root, &path, globs, visited, files);
+            continue;
+        }
+
+        if matches_globs(root, &path, globs) {
+            files.push(path.to_string_lossy().into_owned());
+        }
+    }
+
+    complete
+}
+
+/// Like [`walk_dir`], but skips the paths that are ignored by `.gitignore` files or the other ignore files of git.
+///
+/// Like git, it does not follow symbolic links to directories and skips the `.git` directories.
+fn walk_dir_ignored(root: &Path, globs: &[glob::Pattern], files: &mut Vec<String>) -> bool {
+    let walk = ignore::WalkBuilder::new(root)
+        .standard_filters(false)
+        .git_ignore(true)
+        .git_global(true)
+        .git_exclude(true)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .sort_by_file_path(Path::cmp)
+        .build();
+    let mut complete = true;
+
+    for entry in walk {
+        match entry {
+            Ok(entry) if !entry.path().is_dir() => {
+                if matches_globs(root, entry.path(), globs) {
+                    files.push(entry.path().to_string_lossy().into_owned());
+                }
             }
-        } else {
-            stdin.read_to_end(&mut buffer).unwrap();
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("Error: Could not read a path in '{}': {}", root.display(), err);
+                complete = false;
+            }
+        }
+    }
+
+    complete
+}
+
+/// Returns whether there are no globs, or any of them matches the path of the file relative to `root` or its name.
+fn matches_globs(root: &Path, path: &Path, globs: &[glob::Pattern]) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let name = path.file_name().map(Path::new).unwrap_or(path);
+    globs.is_empty()
+        || globs
+            .iter()
+            .any(|glob| glob.matches_path(relative) || glob.matches_path(name))
+}
+
+/// The optional features of the crate, and whether they were enabled at compile time.
+const FEATURES: &[(&str, bool)] = &[
+    ("tui", cfg!(feature = "tui")),
+    ("render", cfg!(feature = "render")),
+    ("clipboard", cfg!(feature = "clipboard")),
+    ("compression", cfg!(feature = "compression")),
+];
+
+fn list_features() -> io::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{} {}", crate_name!(), crate_version!())?;
 
-            for byte in &buffer {
-                formatter.write_byte(*byte);
+    for (name, enabled) in FEATURES {
+        writeln!(stdout, "{}: {}", name, if *enabled { "enabled" } else { "disabled" })?;
+    }
+
+    Ok(())
+}
+
+fn list_fonts(verbose: bool) -> io::Result<()> {
+    let mut font_db = fontdb::Database::new();
+    font_db.load_system_fonts();
+
+    let mut families: BTreeMap<&str, Vec<&fontdb::FaceInfo>> = BTreeMap::new();
+    for face in font_db.faces() {
+        for (family, _) in &face.families {
+            families.entry(family).or_default().push(face);
+        }
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    for (family, faces) in families {
+        writeln!(stdout, "{}", family)?;
+
+        if verbose {
+            for face in faces {
+                let path = match &face.source {
+                    fontdb::Source::File(path) | fontdb::Source::SharedFile(path, _) => path.display().to_string(),
+                    fontdb::Source::Binary(_) => String::from("<memory>"),
+                };
+
+                writeln!(
+                    stdout,
+                    "    {:?}, weight {}, {:?}, {} (index {})",
+                    face.style, face.weight.0, face.stretch, path, face.index
+                )?;
             }
         }
-    } else {
-        // Init font database
-        let mut font_db = fontdb::Database::new();
-        font_db.load_system_fonts();
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Format,
+    DumpBytes,
+    Decode,
+    /// The inverse of `--encode base64`.
+    DecodeBase64,
+    /// The whole input in the text encoding, instead of formatted by character.
+    Encode(TextEncoding),
+    Stats,
+}
+
+fn parse_font_weight(value: &str) -> Result<fontdb::Weight, String> {
+    let weight = match value.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+        "thin" | "hairline" => fontdb::Weight::THIN,
+        "extralight" | "ultralight" => fontdb::Weight::EXTRA_LIGHT,
+        "light" => fontdb::Weight::LIGHT,
+        "normal" | "regular" => fontdb::Weight::NORMAL,
+        "medium" => fontdb::Weight::MEDIUM,
+        "semibold" | "demibold" => fontdb::Weight::SEMIBOLD,
+        "bold" => fontdb::Weight::BOLD,
+        "extrabold" | "ultrabold" => fontdb::Weight::EXTRA_BOLD,
+        "black" | "heavy" => fontdb::Weight::BLACK,
+        number => match number.parse::<u16>() {
+            Ok(weight @ 1..=1000) => fontdb::Weight(weight),
+            _ => return Err(String::from("expected a number from 1 to 1000 or a weight name")),
+        },
+    };
 
-        // Load fonts
-        let font_sources: Vec<_> = formatter
-            .fontnames
-            .split(',')
-            .map(|fontname| get_font_source(&font_db, fontname))
-            .collect();
+    Ok(weight)
+}
 
-        let mut fonts = Vec::with_capacity(font_sources.len());
-        for src in &font_sources {
-            fonts.push(load_font(src));
+/// Parses `CHAR=TEXT`, where both may contain escapes like `\t`, `\n` or `\u{a0}`.
+fn parse_replacement(value: &str) -> Result<(char, String), String> {
+    let (key, text) = match value.strip_prefix('\\') {
+        // The escape of the character ends before the first `=`, which may be the character itself
+        Some(_) => value.split_once('=').ok_or("expected CHAR=TEXT")?,
+        None => {
+            let key_len = value.chars().next().ok_or("expected CHAR=TEXT")?.len_utf8();
+            let (key, rest) = value.split_at(key_len);
+            (key, rest.strip_prefix('=').ok_or("expected CHAR=TEXT")?)
         }
+    };
+
+    let mut chars = unescape(key)?.into_iter();
+    match (chars.next(), chars.next()) {
+        (Some(char), None) => Ok((char, unescape(text)?.into_iter().collect())),
+        _ => Err(String::from("expected a single character before =")),
+    }
+}
 
-        if line_by_line {
-            loop {
-                let read = stdin.read_until(b'\n', &mut buffer).unwrap();
+/// Replaces the escapes `\n`, `\r`, `\t`, `\0`, `\\` and `\u{...}` in the value.
+fn unescape(value: &str) -> Result<Vec<char>, String> {
+    let mut chars = value.chars();
+    let mut unescaped = Vec::new();
 
-                if read == 0 {
-                    break;
-                }
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            unescaped.push(char);
+            continue;
+        }
+
+        let char = match chars.next() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('u') => {
+                let error = || String::from("expected a codepoint like \\u{a0}");
+                let (digits, rest) = chars
+                    .as_str()
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .ok_or_else(error)?;
+                let char = u32::from_str_radix(digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(error)?;
 
-                formatter.process_str(&fonts, &buffer[..read]);
-                formatter.stdout_lock.flush().unwrap();
-                buffer.clear();
+                chars = rest.chars();
+                char
             }
-        } else {
-            stdin.read_to_end(&mut buffer).unwrap();
-            formatter.process_str(&fonts, &buffer);
+            _ => return Err(String::from("expected an escape like \\t, \\n or \\u{a0}")),
+        };
+
+        unescaped.push(char);
+    }
+
+    Ok(unescaped)
+}
+
+fn parse_char(value: &str) -> Result<char, String> {
+    let mut chars = value.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(char), None) => Ok(char),
+        _ => Err(String::from("expected a single character")),
+    }
+}
+
+fn parse_size(value: &str) -> Result<u64, String> {
+    let size = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(digits) => u64::from_str_radix(digits, 16),
+        None => value.parse(),
+    };
+
+    size.map_err(|_| String::from("expected a number of bytes, like 4096 or 0x1000"))
+}
+
+#[derive(Clone, Copy)]
+enum Highlight {
+    Byte(u8),
+    Char(char),
+}
+
+/// Parses a hex code point like `FEFF` or `U+FEFF`.
+fn code_point(value: &str) -> Option<u32> {
+    let digits = value
+        .strip_prefix("U+")
+        .or_else(|| value.strip_prefix("u+"))
+        .unwrap_or(value);
+    u32::from_str_radix(digits, 16)
+        .ok()
+        .filter(|code_point| *code_point <= u32::from(char::MAX))
+}
+
+fn parse_code_point(value: &str) -> Result<char, String> {
+    code_point(value)
+        .and_then(char::from_u32)
+        .ok_or_else(|| String::from("expected a character like U+FEFF"))
+}
+
+fn parse_range(value: &str) -> Result<RangeInclusive<u32>, String> {
+    let range = value
+        .split_once('-')
+        .and_then(|(start, end)| Some(code_point(start)?..=code_point(end)?))
+        .ok_or_else(|| String::from("expected two hex code points like 0400-04FF"))?;
+
+    match range.is_empty() {
+        true => Err(String::from("the start of the range is after its end")),
+        false => Ok(range),
+    }
+}
+
+fn parse_highlight(value: &str) -> Result<Highlight, String> {
+    if let Some(digits) = value.strip_prefix("\\x") {
+        if digits.len() != 2 || !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+            return Err(String::from("expected two hex digits after \\x"));
+        }
+
+        return Ok(Highlight::Byte(u8::from_str_radix(digits, 16).unwrap()));
+    }
+
+    value
+        .strip_prefix("U+")
+        .or_else(|| value.strip_prefix("u+"))
+        .filter(|digits| !digits.is_empty() && digits.bytes().all(|digit| digit.is_ascii_hexdigit()))
+        .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+        .and_then(char::from_u32)
+        .map(Highlight::Char)
+        .ok_or_else(|| String::from("expected a byte like \\xff or a character like U+FEFF"))
+}
+
+fn parse_max_line(value: &str) -> Result<u64, String> {
+    match parse_size(value)? {
+        0 => Err(String::from("expected at least 1 byte")),
+        size => Ok(size),
+    }
+}
+
+fn parse_delimiter(value: &str) -> Result<u8, String> {
+    if let Some(digits) = value.strip_prefix("\\x") {
+        if digits.len() != 2 || !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+            return Err(String::from("expected two hex digits after \\x"));
         }
+
+        return Ok(u8::from_str_radix(digits, 16).unwrap());
+    }
+
+    match value.as_bytes() {
+        [byte] => Ok(*byte),
+        _ => Err(String::from("expected a single byte, like ; or \\x1e")),
     }
+}
+
+/// Returns the value of a prefix option and warns if the prefix would not be printed as is.
+fn prefix_arg(matches: &ArgMatches, id: &str) -> String {
+    let prefix = matches.get_one::<String>(id).unwrap();
 
-    // Final newline for terminal output, if there is no ending newline
-    if formatter.stdout_lock.is_terminal()
-        && (formatter.all_as_hex || formatter.newline_escaped || formatter.newline_as_hex)
+    if !matches.get_flag("quiet")
+        && prefix
+            .chars()
+            .any(|c| c.is_control() || (c != ' ' && c.is_whitespace()))
     {
-        writeln!(formatter.stdout_lock).unwrap();
+        eprintln!(
+            "Warning: --{} '{}' contains characters that are escaped themselves",
+            id,
+            prefix.escape_debug()
+        );
     }
+
+    prefix.clone()
 }
 
-#[allow(clippy::large_enum_variant)]
-enum FontCow<'a> {
-    FontVec(ab_glyph::FontVec),
-    FontRef(ab_glyph::FontRef<'a>),
+const CHUNK_SIZE: usize = 64 * 1024;
+
+enum Input {
+    Mapped(memmap2::Mmap),
+    Reader(Box<dyn BufRead>),
 }
 
-impl FontCow<'_> {
-    fn glyph_id(&self, c: char) -> ab_glyph::GlyphId {
-        match self {
-            FontCow::FontVec(f) => f.glyph_id(c),
-            FontCow::FontRef(f) => f.glyph_id(c),
+/// Opens the file at `path`, where `-` stands for stdin, after skipping `skip` bytes and limited to `length` bytes.
+///
+/// Regular files are memory-mapped if `map` is set, other files such as pipes or devices are read. A compressed input
+/// is decompressed with `decompress`, and the bytes are skipped and counted in the decompressed input.
+fn open_input(
+    path: &str,
+    map: bool,
+    skip: u64,
+    length: Option<u64>,
+    decompress: Option<Decompress>,
+) -> std::io::Result<Input> {
+    let length = length.unwrap_or(u64::MAX);
+
+    if path == "-" {
+        let mut stdin = std::io::stdin().lock();
+        let compression = match decompress {
+            Some(decompress) => decompress.compression(path, stdin.fill_buf()?),
+            None => None,
+        };
+        let stdin = match compression {
+            Some(compression) => decompress::decoder(Box::new(stdin), compression)?,
+            None => Box::new(stdin),
+        };
+        return read_input(stdin, skip, length);
+    }
+
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+
+    // The start of the file is only peeked at, so a regular file without compression can still be mapped
+    if let Some(decompress) = decompress {
+        let mut reader = BufReader::with_capacity(CHUNK_SIZE, file);
+        match decompress.compression(path, reader.fill_buf()?) {
+            Some(compression) => return read_input(decompress::decoder(Box::new(reader), compression)?, skip, length),
+            None if !metadata.is_file() => return read_input(Box::new(reader), skip, length),
+            None => file = reader.into_inner(),
         }
     }
-}
 
-fn get_font_source(font_db: &fontdb::Database, fontname: &str) -> fontdb::Source {
-    let query = fontdb::Query {
-        families: &[fontdb::Family::Name(fontname)],
-        ..fontdb::Query::default()
-    };
+    if map && metadata.is_file() {
+        let start = skip.min(metadata.len());
+        let len = (metadata.len() - start).min(length);
 
-    match font_db.query(&query) {
-        Some(id) => {
-            let (src, _) = font_db.face_source(id).unwrap();
-            src
+        // SAFETY: The file is only read, though its content is undefined if another process modifies it meanwhile.
+        // Mapping fails for empty ranges on some platforms, which are then read as usual.
+        if let Ok(mmap) = unsafe { memmap2::MmapOptions::new().offset(start).len(len as usize).map(&file) } {
+            return Ok(Input::Mapped(mmap));
         }
+    }
+
+    // Only regular files can seek, other files have to be read up to the start
+    if metadata.is_file() {
+        file.seek(SeekFrom::Start(skip))?;
+    } else {
+        std::io::copy(&mut Read::by_ref(&mut file).take(skip), &mut std::io::sink())?;
+    }
+
+    Ok(Input::Reader(Box::new(BufReader::with_capacity(
+        CHUNK_SIZE,
+        file.take(length),
+    ))))
+}
+
+/// Reads the input after skipping `skip` bytes, limited to `length` bytes.
+fn read_input(mut reader: Box<dyn BufRead>, skip: u64, length: u64) -> std::io::Result<Input> {
+    std::io::copy(&mut reader.by_ref().take(skip), &mut std::io::sink())?;
+    Ok(Input::Reader(Box::new(reader.take(length))))
+}
+
+/// Draws the distinct characters of the text or standard input with the fonts, and exits if the image cannot be written.
+#[cfg(feature = "render")]
+fn render_chars(matches: &ArgMatches, fonts: &[FontCow]) -> io::Result<ExitCode> {
+    let text = match matches.get_one::<String>("text") {
+        Some(text) => text.clone(),
         None => {
-            eprintln!("Error: Font '{}' not found", fontname);
+            let mut input = Vec::new();
+            if let Err(err) = std::io::stdin().lock().read_to_end(&mut input) {
+                eprintln!("Error: Could not read 'standard input': {}", err);
+                std::process::exit(1);
+            }
+
+            String::from_utf8_lossy(&input).into_owned()
+        }
+    };
+
+    let mut seen = std::collections::HashSet::new();
+    let chars: Vec<char> = text
+        .chars()
+        .filter(|char| !char.is_control() && seen.insert(*char))
+        .collect();
+
+    let path = matches.get_one::<String>("output").unwrap();
+    let file = match File::create(path) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("Error: Could not create '{}': {}", path, err);
             std::process::exit(1);
         }
+    };
+
+    let size = matches.get_one::<NonZeroU32>("size").unwrap().get();
+    let columns = matches.get_one::<NonZeroUsize>("columns").unwrap().get();
+    if let Err(err) = render::write_png(BufWriter::new(file), fonts, &chars, size, columns) {
+        eprintln!("Error: Could not write '{}': {}", path, err);
+        std::process::exit(1);
+    }
+
+    let missing = chars
+        .iter()
+        .filter(|char| !hexv::is_char_in_fonts(fonts, **char))
+        .count();
+    Ok(if missing > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+#[cfg(not(feature = "render"))]
+fn render_chars(_matches: &ArgMatches, _fonts: &[FontCow]) -> io::Result<ExitCode> {
+    eprintln!("Error: hexv was built without the render feature");
+    std::process::exit(1);
+}
+
+/// Returns the bytes of the text of --expr or the clipboard as input, after skipping `skip` bytes and limited to
+/// `length` bytes.
+fn text_input(text: &str, skip: u64, length: Option<u64>) -> Input {
+    let mut cursor = io::Cursor::new(text.as_bytes().to_vec());
+    cursor.set_position(skip);
+    Input::Reader(Box::new(cursor.take(length.unwrap_or(u64::MAX))))
+}
+
+/// Parses the hex tokens of the input, and returns the input of the bytes they stand for.
+fn hex_input(input: Input) -> io::Result<Input> {
+    let bytes = match input {
+        Input::Mapped(mmap) => decode_hex_tokens(&mmap),
+        Input::Reader(mut reader) => {
+            let mut text = Vec::new();
+            reader.read_to_end(&mut text)?;
+            decode_hex_tokens(&text)
+        }
+    }
+    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    Ok(Input::Reader(Box::new(io::Cursor::new(bytes))))
+}
+
+/// Detects the encoding from the start of the input, and returns the input that still starts there.
+fn detect_input(input: Input) -> io::Result<(Detection, Input)> {
+    match input {
+        Input::Mapped(mmap) => {
+            let detection = Detection::new(&mmap[..mmap.len().min(detect::SAMPLE_LEN as usize)]);
+            Ok((detection, Input::Mapped(mmap)))
+        }
+        Input::Reader(mut reader) => {
+            let mut sample = Vec::new();
+            Read::by_ref(&mut reader)
+                .take(detect::SAMPLE_LEN)
+                .read_to_end(&mut sample)?;
+            let detection = Detection::new(&sample);
+            Ok((
+                detection,
+                Input::Reader(Box::new(io::Cursor::new(sample).chain(reader))),
+            ))
+        }
     }
 }
 
-fn load_font(font_source: &fontdb::Source) -> FontCow<'_> {
-    match font_source {
-        fontdb::Source::Binary(bin) | fontdb::Source::SharedFile(_, bin) => {
-            FontCow::FontRef(ab_glyph::FontRef::try_from_slice(bin.deref().as_ref()).expect("Could not load font"))
+/// Prints the detected encoding to stderr, and reads the input with it if `use_detected` is set.
+fn report_detection<W: Write>(
+    formatter: &mut Formatter<W>,
+    name: &str,
+    detection: &Detection,
+    use_detected: bool,
+    quiet: bool,
+) {
+    if detection.charset.is_empty() {
+        if !quiet {
+            eprintln!("Warning: The encoding of '{}' could not be detected", name);
         }
-        fontdb::Source::File(path) => FontCow::FontVec(
-            ab_glyph::FontVec::try_from_vec(std::fs::read(path).expect("Could not read font file"))
-                .expect("Could not load font"),
+        return;
+    }
+
+    eprintln!(
+        "'{}' is most likely {} ({:.0}% confidence)",
+        name,
+        detection.charset,
+        detection.confidence * 100.0
+    );
+
+    if !use_detected {
+        return;
+    }
+
+    match detection.encoding() {
+        Some(encoding) => formatter.set_encoding(encoding),
+        None if !quiet => eprintln!(
+            "Warning: hexv cannot read {}, so '{}' is read with --encoding",
+            detection.charset, name
         ),
+        None => {}
+    }
+}
+
+/// Formats both files and writes the lines in which they differ, returns whether they differ or cannot be read.
+fn diff_files<W: Write>(
+    formatter: &mut Formatter<W>,
+    fonts: &[FontCow],
+    font_names: &[String],
+    paths: [&str; 2],
+    skip: u64,
+    length: Option<u64>,
+    decompress: Option<Decompress>,
+) -> io::Result<bool> {
+    // The differences are colored instead of the escapes
+    let options = FormatterOptions {
+        color: false,
+        ..formatter.options().clone()
+    };
+    let mut formatted = Vec::with_capacity(paths.len());
+
+    for path in paths {
+        let mut file_formatter = Formatter::new(Vec::new(), fonts, options.clone())
+            .with_font_names(font_names.to_vec())
+            .with_glyph_log(Box::new(io::stderr()));
+        file_formatter.set_start_offset(skip as usize);
+
+        let chars = open_input(path, true, skip, length, decompress).and_then(|input| match input {
+            Input::Mapped(mmap) => file_formatter.format_chars(&mmap),
+            Input::Reader(mut reader) => {
+                let mut data = Vec::new();
+                reader.read_to_end(&mut data)?;
+                file_formatter.format_chars(&data)
+            }
+        });
+
+        match chars {
+            Ok(chars) => formatted.push(chars),
+            Err(err) => {
+                eprintln!("Error: Could not read '{}': {}", path, err);
+                return Ok(true);
+            }
+        }
+    }
+
+    let color = formatter.options().color;
+    diff::write_diff(formatter.get_mut(), &formatted[0], &formatted[1], color)
+}
+
+/// How the input is split into lines with --line-by-line.
+#[derive(Clone, Copy)]
+struct LineSplit {
+    /// The byte that ends each line.
+    delimiter: u8,
+    /// The length after which a line is processed in parts.
+    max_len: u64,
+}
+
+/// When the output is flushed while the input is processed, besides at the end.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Flush {
+    /// After each line, or after each read if the input is not read by lines.
+    Line,
+    /// At most once per [`FLUSH_INTERVAL`].
+    Interval,
+    /// Only when the buffer of the output is full.
+    Never,
+}
+
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+struct Flusher {
+    flush: Flush,
+    /// When the output was last flushed with [`Flush::Interval`].
+    last: Instant,
+}
+
+impl Flusher {
+    /// Flushes the output after a line or a read, if it is time to.
+    fn flush(&mut self, output: &mut impl Write) -> io::Result<()> {
+        match self.flush {
+            Flush::Line => output.flush(),
+            Flush::Interval if self.last.elapsed() >= FLUSH_INTERVAL => {
+                self.last = Instant::now();
+                output.flush()
+            }
+            Flush::Interval | Flush::Never => Ok(()),
+        }
+    }
+}
+
+/// How long the phases of the program took, which are only measured with `--timings`.
+#[derive(Default)]
+struct Timings {
+    enabled: bool,
+    fonts: Duration,
+    /// Mapped files are only read while they are formatted, so this is mostly the time of opening them.
+    reading: Duration,
+    formatting: Duration,
+    /// The number of bytes of the inputs that were formatted.
+    bytes: u64,
+}
+
+impl Timings {
+    /// Returns the time to measure a phase from, or `None` if the timings are not measured.
+    fn start(&self) -> Option<Instant> {
+        self.enabled.then(Instant::now)
+    }
+}
+
+/// Returns the time since the start of a phase, which is zero if it was not measured.
+fn elapsed(start: Option<Instant>) -> Duration {
+    start.map_or(Duration::ZERO, |start| start.elapsed())
+}
+
+fn print_timings(timings: &Timings) {
+    let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let total = timings.reading + timings.formatting;
+
+    eprintln!("Loading fonts: {:.1} ms", millis(timings.fonts));
+    eprintln!("Reading: {:.1} ms", millis(timings.reading));
+    eprintln!("Formatting: {:.1} ms", millis(timings.formatting));
+
+    if !total.is_zero() {
+        eprintln!(
+            "Throughput: {:.1} MB/s ({} bytes)",
+            timings.bytes as f64 / 1e6 / total.as_secs_f64(),
+            timings.bytes
+        );
+    }
+}
+
+/// An error of processing an input, where only errors of reading end the input instead of the program.
+enum InputError {
+    Read(io::Error),
+    Write(io::Error),
+}
+
+#[allow(clippy::too_many_arguments)]
+fn process_input<W: Write>(
+    formatter: &mut Formatter<W>,
+    stats: &mut ByteStats,
+    input: Input,
+    buffer: &mut Vec<u8>,
+    mode: Mode,
+    lines: Option<LineSplit>,
+    mut line_number: Option<&mut usize>,
+    flusher: &mut Flusher,
+    timings: &mut Timings,
+) -> Result<(), InputError> {
+    // Each input is encoded on its own, so it ends with its own padding
+    let mut encoder = match mode {
+        Mode::Encode(encoding) => Some(Encoder::new(encoding, formatter.options().wrap)),
+        _ => None,
+    };
+
+    let mut input = match input {
+        Input::Mapped(mmap) => {
+            let start = timings.start();
+            if matches!(mode, Mode::Decode | Mode::DecodeBase64) {
+                write_decoded(formatter, mode, &mmap)?;
+            } else {
+                feed(formatter, stats, mode, encoder.as_mut(), &mmap).map_err(InputError::Write)?;
+            }
+
+            finish(formatter, encoder.as_mut()).map_err(InputError::Write)?;
+            timings.formatting += elapsed(start);
+            timings.bytes += mmap.len() as u64;
+            return Ok(());
+        }
+        Input::Reader(reader) => reader,
+    };
+
+    if matches!(mode, Mode::Decode | Mode::DecodeBase64) {
+        let start = timings.start();
+        input.read_to_end(buffer).map_err(InputError::Read)?;
+        timings.reading += elapsed(start);
+
+        let start = timings.start();
+        write_decoded(formatter, mode, buffer)?;
+        timings.formatting += elapsed(start);
+        timings.bytes += buffer.len() as u64;
+    } else if let Some(lines) = lines {
+        // Whether the next part is the start of a line, instead of the continuation of a line that was too long
+        let mut at_line_start = true;
+
+        loop {
+            let start = timings.start();
+            let read = Read::by_ref(&mut input)
+                .take(lines.max_len)
+                .read_until(lines.delimiter, buffer)
+                .map_err(InputError::Read)?;
+            timings.reading += elapsed(start);
+
+            if read == 0 {
+                break;
+            }
+
+            if let Some(line_number) = line_number.as_deref_mut().filter(|_| at_line_start) {
+                formatter
+                    .write_prefix(&format!("{:>6}: ", line_number))
+                    .map_err(InputError::Write)?;
+                *line_number += 1;
+            }
+
+            // A character that is cut by the end of a part is completed by the next part, as the formatter
+            // keeps the start of it until then
+            // The NUL that ends a record is kept as is, so the output can be split at it again, like with xargs -0
+            let (record, delimiter) = match buffer[..read].split_last() {
+                Some((b'\0', record))
+                    if lines.delimiter == b'\0' && mode == Mode::Format && !formatter.options().json =>
+                {
+                    (record, Some(b'\0'))
+                }
+                _ => (&buffer[..read], None),
+            };
+
+            let start = timings.start();
+            feed(formatter, stats, mode, encoder.as_mut(), record).map_err(InputError::Write)?;
+            if let Some(delimiter) = delimiter {
+                formatter.end_record(delimiter).map_err(InputError::Write)?;
+            }
+            flusher.flush(formatter.get_mut()).map_err(InputError::Write)?;
+            timings.formatting += elapsed(start);
+            timings.bytes += read as u64;
+            at_line_start = buffer.last() == Some(&lines.delimiter) || (read as u64) < lines.max_len;
+            buffer.clear();
+        }
+    } else {
+        // Process the input in chunks, so it is never read into memory as a whole
+        loop {
+            let start = timings.start();
+            let chunk = match input.fill_buf() {
+                Ok(chunk) => chunk,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(InputError::Read(err)),
+            };
+            timings.reading += elapsed(start);
+
+            if chunk.is_empty() {
+                break;
+            }
+
+            let len = chunk.len();
+            let start = timings.start();
+            feed(formatter, stats, mode, encoder.as_mut(), chunk).map_err(InputError::Write)?;
+            flusher.flush(formatter.get_mut()).map_err(InputError::Write)?;
+            timings.formatting += elapsed(start);
+            timings.bytes += len as u64;
+            input.consume(len);
+        }
+    }
+
+    let start = timings.start();
+    finish(formatter, encoder.as_mut()).map_err(InputError::Write)?;
+    timings.formatting += elapsed(start);
+    Ok(())
+}
+
+fn feed<W: Write>(
+    formatter: &mut Formatter<W>,
+    stats: &mut ByteStats,
+    mode: Mode,
+    encoder: Option<&mut Encoder>,
+    bytes: &[u8],
+) -> io::Result<()> {
+    if let Some(encoder) = encoder {
+        return encoder.write(formatter.get_mut(), bytes);
+    }
+
+    match mode {
+        Mode::DumpBytes => formatter.dump_bytes(bytes),
+        Mode::Stats => {
+            stats.add(bytes);
+            Ok(())
+        }
+        _ => formatter.process_str(bytes),
+    }
+}
+
+fn finish<W: Write>(formatter: &mut Formatter<W>, encoder: Option<&mut Encoder>) -> io::Result<()> {
+    match encoder {
+        Some(encoder) => encoder.finish(formatter.get_mut()),
+        None => formatter.finish(),
     }
 }
+
+fn write_decoded<W: Write>(formatter: &mut Formatter<W>, mode: Mode, input: &[u8]) -> Result<(), InputError> {
+    // Text that cannot be decoded is an error of the input
+    let decoded = match mode {
+        Mode::DecodeBase64 => decode_base64(input).map_err(|err| err.to_string()),
+        _ => decode(input, formatter.options()).map_err(|err| err.to_string()),
+    }
+    .map_err(|err| InputError::Read(io::Error::new(io::ErrorKind::InvalidData, err)))?;
+
+    formatter.get_mut().write_all(&decoded).map_err(InputError::Write)
+}
@@ -0,0 +1,151 @@
+use std::io::{self, Write};
+
+use bstr::ByteSlice;
+
+use crate::chars;
+
+/// Passes the written text on to the inner writer, and keeps track of the column it ends in.
+pub(crate) struct Output<W: Write> {
+    pub inner: W,
+    /// The display width of the text after the last new line, where wide characters like CJK take two columns.
+    pub column: usize,
+    /// The number of columns between tab stops, to know the column after a tab.
+    pub tab_width: usize,
+    /// The number of bytes written so far, without the line breaks of `wrap`.
+    pub written: usize,
+    pub ends_with_newline: bool,
+    /// Break the line before a unit of text that would end after this column.
+    wrap: Option<usize>,
+    /// The number of spaces at the start of the lines that continue a wrapped line.
+    wrap_indent: usize,
+    /// The text of the current unit with `wrap`, and whether each part takes columns, see [`Output::start_unit`].
+    unit: Option<Vec<(bool, Vec<u8>)>>,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(inner: W, tab_width: usize) -> Self {
+        Self {
+            inner,
+            column: 0,
+            tab_width,
+            written: 0,
+            ends_with_newline: false,
+            wrap: None,
+            wrap_indent: 0,
+            unit: None,
+        }
+    }
+
+    pub fn with_wrap(mut self, wrap: Option<usize>, wrap_indent: usize) -> Self {
+        self.wrap = wrap;
+        self.wrap_indent = wrap_indent;
+        self
+    }
+
+    /// Keeps the text written until [`Output::end_unit`] together, so a line is never wrapped inside of it.
+    pub fn start_unit(&mut self) {
+        if self.wrap.is_some() {
+            self.unit = Some(Vec::new());
+        }
+    }
+
+    /// Writes the text of the current unit, after a line break if it would end after the wrap column.
+    pub fn end_unit(&mut self) -> io::Result<()> {
+        let (Some(unit), Some(wrap)) = (self.unit.take(), self.wrap) else {
+            return Ok(());
+        };
+
+        // Only the text up to a new line in the unit has to fit into the current line
+        let text: Vec<u8> = unit
+            .iter()
+            .filter(|(counted, _)| *counted)
+            .flat_map(|(_, bytes)| bytes.iter().copied())
+            .collect();
+        let end = text
+            .to_str_lossy()
+            .chars()
+            .take_while(|char| *char != '\n')
+            .fold(self.column, |column, char| {
+                chars::next_column(column, char, self.tab_width)
+            });
+
+        if self.column > 0 && end > wrap {
+            let line_break = format!("\n{:1$}", "", self.wrap_indent);
+            self.inner.write_all(line_break.as_bytes())?;
+            self.advance(line_break.as_bytes());
+        }
+
+        for (counted, bytes) in unit {
+            self.inner.write_all(&bytes)?;
+
+            if counted {
+                self.advance(&bytes);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes text that takes no columns, like the ANSI codes of colors.
+    pub fn write_uncounted(&mut self, text: &str) -> io::Result<()> {
+        match &mut self.unit {
+            Some(unit) => {
+                unit.push((false, text.as_bytes().to_vec()));
+                Ok(())
+            }
+            None => self.inner.write_all(text.as_bytes()),
+        }
+    }
+
+    /// Updates the column and whether the output ends with a new line after the bytes are written.
+    fn advance(&mut self, bytes: &[u8]) {
+        if let Some(last) = bytes.last() {
+            self.ends_with_newline = *last == b'\n';
+        }
+
+        self.column = bytes.to_str_lossy().chars().fold(self.column, |column, char| {
+            chars::next_column(column, char, self.tab_width)
+        });
+    }
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The bytes of a unit count as written right away, as the byte separators depend on it
+        if let Some(unit) = &mut self.unit {
+            unit.push((true, buf.to_vec()));
+            self.written += buf.len();
+            return Ok(buf.len());
+        }
+
+        let written = self.inner.write(buf)?;
+        self.written += written;
+        self.advance(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(text: &str, tab_width: usize) -> usize {
+        let mut output = Output::new(Vec::new(), tab_width);
+        output.write_all(text.as_bytes()).unwrap();
+        output.column
+    }
+
+    #[test]
+    fn column_after_wide_chars_and_tabs() {
+        assert_eq!(column("中文\t", 4), 8);
+        assert_eq!(column("中文\t", 8), 8);
+        assert_eq!(column("中\t", 4), 4);
+        assert_eq!(column("中\t", 8), 8);
+        assert_eq!(column("a\n中文\t中\t", 8), 16);
+        assert_eq!(column("中e\u{301}\t", 4), 4);
+    }
+}
@@ -0,0 +1,178 @@
+use std::io::{self, Write};
+
+use ab_glyph::{point, Font, PxScale, ScaleFont};
+use hexv::{find_font_for_char, FontCow};
+
+const BACKGROUND: [u8; 3] = [0xff, 0xff, 0xff];
+const GLYPH: [u8; 3] = [0x00, 0x00, 0x00];
+const LABEL: [u8; 3] = [0x60, 0x60, 0x60];
+const MISSING: [u8; 3] = [0xd0, 0x20, 0x20];
+
+/// The characters that are needed to label each cell with the codepoint.
+const LABEL_CHARS: &str = "U+0123456789ABCDEF";
+
+/// Draws the glyphs of the characters in a grid of cells and writes it as a PNG image.
+///
+/// Each cell is labeled with the codepoint of its character, and the cells of characters that none of the fonts has are
+/// crossed out. The fonts have to be loaded with their outlines, the cached coverage cannot be drawn.
+pub fn write_png(output: impl Write, fonts: &[FontCow], chars: &[char], size: u32, columns: usize) -> io::Result<()> {
+    let size = size as usize;
+    let padding = size / 8;
+    let label_size = (size / 4).max(8);
+    let cell_width = size + 2 * padding;
+    let cell_height = cell_width + label_size + padding;
+    let columns = columns.min(chars.len()).max(1);
+    let rows = chars.len().div_ceil(columns).max(1);
+    let mut canvas = Canvas::new(columns * cell_width, rows * cell_height);
+
+    let label_font = fonts
+        .iter()
+        .find(|font| LABEL_CHARS.chars().all(|char| font.has_glyph(char)));
+
+    for (index, char) in chars.iter().enumerate() {
+        let cell = Cell {
+            x: (index % columns) * cell_width,
+            y: (index / columns) * cell_height,
+            width: cell_width,
+            height: cell_height,
+        };
+
+        match find_font_for_char(fonts, *char) {
+            Some(font_index) => {
+                let glyph_area = Cell {
+                    height: cell_width,
+                    ..cell
+                };
+                canvas.draw_font_text(&fonts[font_index], &char.to_string(), size, glyph_area, padding, GLYPH);
+            }
+            None => canvas.draw_cross(&cell, padding, MISSING),
+        }
+
+        if let Some(font) = label_font {
+            let label_area = Cell {
+                y: cell.y + cell_width,
+                height: label_size + padding,
+                ..cell
+            };
+            let label = format!("U+{:04X}", u32::from(*char));
+            canvas.draw_font_text(font, &label, label_size, label_area, 0, LABEL);
+        }
+    }
+
+    let mut encoder = png::Encoder::new(output, canvas.width as u32, canvas.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer
+        .write_image_data(canvas.pixels.as_flattened())
+        .map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+/// An area of the image, which nothing is drawn outside of.
+#[derive(Clone, Copy)]
+struct Cell {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 3]>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![BACKGROUND; width * height],
+        }
+    }
+
+    /// Blends the color into the pixel by its coverage, if the pixel is in the cell.
+    fn blend(&mut self, cell: &Cell, x: i64, y: i64, coverage: f32, color: [u8; 3]) {
+        let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) else {
+            return;
+        };
+
+        if x < cell.x || x >= cell.x + cell.width || y < cell.y || y >= cell.y + cell.height {
+            return;
+        }
+
+        let coverage = coverage.clamp(0.0, 1.0);
+        let pixel = &mut self.pixels[y * self.width + x];
+        for (channel, color) in pixel.iter_mut().zip(color) {
+            *channel = (f32::from(*channel) * (1.0 - coverage) + f32::from(color) * coverage).round() as u8;
+        }
+    }
+
+    fn draw_font_text(&mut self, font: &FontCow, text: &str, size: usize, cell: Cell, padding: usize, color: [u8; 3]) {
+        match font {
+            FontCow::FontVec(font) => self.draw_text(font, text, size, cell, padding, color),
+            FontCow::FontRef(font) => self.draw_text(font, text, size, cell, padding, color),
+            FontCow::Coverage(_) => {}
+        }
+    }
+
+    /// Draws the text centered in the cell, where `size` is the height between the ascent and descent of the font.
+    ///
+    /// Text that is wider than the cell is made smaller to fit it.
+    fn draw_text<F: Font>(&mut self, font: &F, text: &str, size: usize, cell: Cell, padding: usize, color: [u8; 3]) {
+        let text_width = |font: &ab_glyph::PxScaleFont<&F>| -> f32 {
+            text.chars().map(|char| font.h_advance(font.glyph_id(char))).sum()
+        };
+
+        let mut font = font.as_scaled(PxScale::from(size as f32));
+        let mut width = text_width(&font);
+        let max_width = cell.width.saturating_sub(2) as f32;
+        if width > max_width {
+            font = font.font.as_scaled(PxScale::from(size as f32 * max_width / width));
+            width = text_width(&font);
+        }
+
+        let mut x = cell.x as f32 + (cell.width as f32 - width) / 2.0;
+        let baseline = (cell.y + padding) as f32 + font.ascent();
+
+        for char in text.chars() {
+            let glyph_id = font.glyph_id(char);
+            let glyph = glyph_id.with_scale_and_position(font.scale(), point(x, baseline));
+            x += font.h_advance(glyph_id);
+
+            let Some(outline) = font.outline_glyph(glyph) else {
+                continue;
+            };
+
+            let bounds = outline.px_bounds();
+            outline.draw(|glyph_x, glyph_y, coverage| {
+                let x = bounds.min.x as i64 + i64::from(glyph_x);
+                let y = bounds.min.y as i64 + i64::from(glyph_y);
+                self.blend(&cell, x, y, coverage, color);
+            });
+        }
+    }
+
+    /// Draws a box with both diagonals inside the cell, to mark a missing glyph.
+    fn draw_cross(&mut self, cell: &Cell, padding: usize, color: [u8; 3]) {
+        let left = (cell.x + padding) as i64;
+        let top = (cell.y + padding) as i64;
+        let side = (cell.width - 2 * padding) as i64;
+
+        for step in 0..side {
+            for (x, y) in [
+                (left + step, top),
+                (left + step, top + side - 1),
+                (left, top + step),
+                (left + side - 1, top + step),
+                (left + step, top + step),
+                (left + side - 1 - step, top + step),
+            ] {
+                self.blend(cell, x, y, 1.0, color);
+            }
+        }
+    }
+}
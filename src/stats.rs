@@ -0,0 +1,74 @@
+/// How often each byte value occurs in the input.
+#[derive(Clone, Debug)]
+pub struct ByteStats {
+    counts: [u64; 256],
+}
+
+impl Default for ByteStats {
+    fn default() -> Self {
+        Self { counts: [0; 256] }
+    }
+}
+
+impl ByteStats {
+    pub fn add(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.counts[usize::from(*byte)] += 1;
+        }
+    }
+
+    pub fn count(&self, byte: u8) -> u64 {
+        self.counts[usize::from(byte)]
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The Shannon entropy in bits per byte, from 0 for a single repeated byte to 8 for uniformly random bytes.
+    pub fn entropy(&self) -> f64 {
+        let total = self.total() as f64;
+
+        self.counts
+            .iter()
+            .filter(|count| **count > 0)
+            .fold(0.0, |entropy, count| {
+                let probability = *count as f64 / total;
+                entropy - probability * probability.log2()
+            })
+    }
+
+    /// Returns up to `count` of the occurring bytes with their counts, the most frequent first.
+    pub fn most_frequent(&self, count: usize) -> Vec<(u8, u64)> {
+        let mut bytes: Vec<(u8, u64)> = (0..=255)
+            .map(|byte| (byte, self.count(byte)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        bytes.sort_by(|(a_byte, a_count), (b_byte, b_count)| b_count.cmp(a_count).then(a_byte.cmp(b_byte)));
+        bytes.truncate(count);
+        bytes
+    }
+
+    /// Guesses what kind of data the bytes are, by their entropy and the share of ASCII text.
+    pub fn kind(&self) -> &'static str {
+        let total = self.total();
+        let text = (0..=255u8)
+            .filter(|byte| byte.is_ascii_graphic() || byte.is_ascii_whitespace() || *byte >= 0x80)
+            .map(|byte| self.count(byte))
+            .sum::<u64>();
+        let ascii = (0..0x80u8)
+            .filter(|byte| byte.is_ascii_graphic() || byte.is_ascii_whitespace())
+            .map(|byte| self.count(byte))
+            .sum::<u64>();
+
+        if total == 0 {
+            "empty"
+        } else if self.entropy() > 7.5 {
+            "compressed or encrypted"
+        } else if ascii as f64 >= total as f64 * 0.95 || (text == total && ascii as f64 >= total as f64 * 0.5) {
+            "text"
+        } else {
+            "binary"
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::Paragraph,
+    DefaultTerminal,
+};
+
+enum Prompt {
+    None,
+    Search(String),
+    Jump(String),
+}
+
+struct View {
+    lines: Vec<Line<'static>>,
+    /// The lines without their colors, to search in.
+    plain_lines: Vec<String>,
+    top: usize,
+    height: usize,
+    prompt: Prompt,
+    search: Option<String>,
+    /// The line of the last search match, which is highlighted.
+    found: Option<usize>,
+    status: String,
+}
+
+/// Shows the formatted text, with colored escapes, in a view that can be scrolled and searched.
+pub fn run(text: &[u8]) -> std::io::Result<()> {
+    let text = String::from_utf8_lossy(text);
+    let mut view = View {
+        lines: text.lines().map(parse_line).collect(),
+        plain_lines: text.lines().map(strip_colors).collect(),
+        top: 0,
+        height: 0,
+        prompt: Prompt::None,
+        search: None,
+        found: None,
+        status: String::new(),
+    };
+
+    let mut terminal = ratatui::try_init()?;
+    let result = view.run(&mut terminal);
+    ratatui::try_restore()?;
+    result
+}
+
+impl View {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
+        loop {
+            terminal.draw(|frame| {
+                let [text_area, status_area] =
+                    Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+                self.height = usize::from(text_area.height);
+                self.top = self.top.min(self.max_top());
+
+                let lines: Vec<Line> = self
+                    .lines
+                    .iter()
+                    .enumerate()
+                    .skip(self.top)
+                    .take(self.height)
+                    .map(|(index, line)| match self.found {
+                        Some(found) if found == index => line.clone().patch_style(Modifier::REVERSED),
+                        _ => line.clone(),
+                    })
+                    .collect();
+
+                frame.render_widget(Paragraph::new(lines), text_area);
+                frame.render_widget(
+                    Paragraph::new(self.status_line()).style(Style::new().add_modifier(Modifier::REVERSED)),
+                    status_area,
+                );
+            })?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match &mut self.prompt {
+                Prompt::Search(input) | Prompt::Jump(input) => match key.code {
+                    KeyCode::Char(char) => input.push(char),
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    KeyCode::Esc => self.prompt = Prompt::None,
+                    KeyCode::Enter => self.submit(),
+                    _ => {}
+                },
+                Prompt::None => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Down | KeyCode::Char('j') | KeyCode::Enter => self.scroll_down(1),
+                    KeyCode::Up | KeyCode::Char('k') => self.scroll_up(1),
+                    KeyCode::PageDown | KeyCode::Char(' ') => self.scroll_down(self.height.max(1)),
+                    KeyCode::PageUp | KeyCode::Char('b') => self.scroll_up(self.height.max(1)),
+                    KeyCode::Home | KeyCode::Char('g') => self.top = 0,
+                    KeyCode::End | KeyCode::Char('G') => self.top = self.max_top(),
+                    KeyCode::Char('/') => self.prompt = Prompt::Search(String::new()),
+                    KeyCode::Char(':') => self.prompt = Prompt::Jump(String::new()),
+                    KeyCode::Char('n') => self.find(true),
+                    KeyCode::Char('N') => self.find(false),
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    fn status_line(&self) -> String {
+        match &self.prompt {
+            Prompt::Search(input) => format!("/{}", input),
+            Prompt::Jump(input) => format!("Line: {}", input),
+            Prompt::None => {
+                let last = (self.top + self.height).min(self.lines.len());
+                format!(
+                    " Lines {}-{} of {}  {}  [q] quit  [/] search  [n/N] next/previous  [:] go to line",
+                    (self.top + 1).min(last),
+                    last,
+                    self.lines.len(),
+                    self.status
+                )
+            }
+        }
+    }
+
+    fn submit(&mut self) {
+        match std::mem::replace(&mut self.prompt, Prompt::None) {
+            Prompt::Search(input) if !input.is_empty() => {
+                self.search = Some(input);
+                self.found = None;
+                self.find(true);
+            }
+            Prompt::Jump(input) => match input.trim().parse::<usize>() {
+                Ok(line) => {
+                    self.top = line.saturating_sub(1).min(self.max_top());
+                    self.status.clear();
+                }
+                Err(_) => self.status = format!("'{}' is not a line number", input),
+            },
+            _ => {}
+        }
+    }
+
+    /// Scrolls to the next or previous line that contains the search text.
+    fn find(&mut self, forward: bool) {
+        let Some(search) = &self.search else {
+            return;
+        };
+
+        // The first search also looks at the top line
+        let (start, first_step) = match self.found {
+            Some(found) => (found, 1),
+            None => (self.top, 0),
+        };
+        let len = self.plain_lines.len();
+        let found = (first_step..first_step + len)
+            .map(|step| {
+                if forward {
+                    (start + step) % len
+                } else {
+                    (start + len - step) % len
+                }
+            })
+            .find(|index| self.plain_lines[*index].contains(search.as_str()));
+
+        match found {
+            Some(index) => {
+                self.found = Some(index);
+                self.status.clear();
+
+                if index < self.top || index >= self.top + self.height {
+                    self.top = index.min(self.max_top());
+                }
+            }
+            None => self.status = format!("'{}' not found", search),
+        }
+    }
+
+    fn scroll_down(&mut self, lines: usize) {
+        self.top = (self.top + lines).min(self.max_top());
+    }
+
+    fn scroll_up(&mut self, lines: usize) {
+        self.top = self.top.saturating_sub(lines);
+    }
+
+    fn max_top(&self) -> usize {
+        self.lines.len().saturating_sub(self.height)
+    }
+}
+
+/// Turns the ANSI colors of the formatter into styled spans.
+fn parse_line(line: &str) -> Line<'static> {
+    let mut spans = Vec::new();
+    let mut style = Style::new();
+    let mut rest = line;
+
+    while let Some(start) = rest.find("\x1b[") {
+        if start > 0 {
+            spans.push(Span::styled(rest[..start].to_string(), style));
+        }
+
+        let Some(end) = rest[start..].find('m') else {
+            rest = &rest[start..];
+            break;
+        };
+
+        style = match &rest[start + 2..start + end] {
+            "31" => Style::new().fg(Color::Red),
+            "33" => Style::new().fg(Color::Yellow),
+            "36" => Style::new().fg(Color::Cyan),
+            _ => Style::new(),
+        };
+        rest = &rest[start + end + 1..];
+    }
+
+    if !rest.is_empty() {
+        spans.push(Span::styled(rest.to_string(), style));
+    }
+
+    Line::from(spans)
+}
+
+fn strip_colors(line: &str) -> String {
+    parse_line(line)
+        .spans
+        .into_iter()
+        .map(|span| span.content.into_owned())
+        .collect()
+}